@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -48,20 +48,70 @@ pub enum AnimeGenre {
     yuri,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+impl AnimeGenre {
+    /// Slug used by the API in URLs (e.g. genre filters)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnimeGenre::action => "action",
+            AnimeGenre::adventure => "adventure",
+            AnimeGenre::cars => "cars",
+            AnimeGenre::comedy => "comedy",
+            AnimeGenre::dementia => "dementia",
+            AnimeGenre::demons => "demons",
+            AnimeGenre::drama => "drama",
+            AnimeGenre::ecchi => "ecchi",
+            AnimeGenre::fantasy => "fantasy",
+            AnimeGenre::game => "game",
+            AnimeGenre::harem => "harem",
+            AnimeGenre::historical => "historical",
+            AnimeGenre::horror => "horror",
+            AnimeGenre::josei => "josei",
+            AnimeGenre::kids => "kids",
+            AnimeGenre::magic => "magic",
+            AnimeGenre::martial_arts => "martial-arts",
+            AnimeGenre::mecha => "mecha",
+            AnimeGenre::military => "military",
+            AnimeGenre::music => "music",
+            AnimeGenre::mystery => "mystery",
+            AnimeGenre::parody => "parody",
+            AnimeGenre::police => "police",
+            AnimeGenre::psychological => "psychological",
+            AnimeGenre::romance => "romance",
+            AnimeGenre::samurai => "samurai",
+            AnimeGenre::school => "school",
+            AnimeGenre::sci_fi => "sci-fi",
+            AnimeGenre::seinen => "seinen",
+            AnimeGenre::shoujo => "shoujo",
+            AnimeGenre::shoujo_ai => "shoujo-ai",
+            AnimeGenre::shounen => "shounen",
+            AnimeGenre::shounen_ai => "shounen-ai",
+            AnimeGenre::slice_of_life => "slice-of-life",
+            AnimeGenre::space => "space",
+            AnimeGenre::sports => "sports",
+            AnimeGenre::super_power => "super-power",
+            AnimeGenre::supernatural => "supernatural",
+            AnimeGenre::thriller => "thriller",
+            AnimeGenre::vampire => "vampire",
+            AnimeGenre::yaoi => "yaoi",
+            AnimeGenre::yuri => "yuri",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum AnimeType {
-    #[serde(rename(deserialize = "tv"))]
+    #[serde(rename = "tv")]
     Tv,
-    #[serde(rename(deserialize = "special"))]
+    #[serde(rename = "special")]
     Special,
-    #[serde(rename(deserialize = "ova"))]
+    #[serde(rename = "ova")]
     Ova,
-    #[serde(rename(deserialize = "movie"))]
+    #[serde(rename = "movie")]
     Movie,
     // will be "music video"
-    #[serde(rename(deserialize = "music video"))]
+    #[serde(rename = "music video")]
     MusicVideo,
-    #[serde(rename(deserialize = "ona"))]
+    #[serde(rename = "ona")]
     Ona,
 }
@@ -48,7 +48,7 @@ pub struct CalendarItem {
 /// * month: from 1 to 12 where 1 is january
 pub fn get_monthly_request(what: MediaType, year: u16, month: u8) -> String {
     let mut result = String::from(API_URL);
-    result.push_str("calendar/");
+    result.push_str("/calendar/");
     result.push_str(&year.to_string());
     result.push('/');
     result.push_str(&month.to_string());
@@ -56,7 +56,7 @@ pub fn get_monthly_request(what: MediaType, year: u16, month: u8) -> String {
         MediaType::Show => result.push_str("/tv.json"),
         MediaType::Anime => result.push_str("/anime.json"),
         MediaType::Movie => result.push_str("/movie_release.json"),
-        MediaType::Episode => {},
+        MediaType::Episode => {}
     }
     result
 }
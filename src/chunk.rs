@@ -0,0 +1,417 @@
+//! Split large sync payloads into batches the API accepts, send them one after the other and merge the responses.
+//!
+//! A show or anime is kept whole by default, its episodes in the same batch even above `ChunkLimits::max_items`: such
+//! an item is sent alone. With `ChunkLimits::with_split_shows` it is split instead into ranges of seasons or
+//! episodes, each sent with the ids of the show. A single item is never split to fit `max_bytes`.
+
+use std::sync::Mutex;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    error::{Result, SimklError},
+    rate_limit::{wait_for_slot, RateLimiter},
+    request::{PreparedRequest, SimklRequest},
+    response::SimklResponse,
+    sync::{
+        AddToListPayload, AddToListResponse, HistoryPayload, HistoryResponse, ListItem, RatedItem,
+        RatingsPayload, RatingsResponse, RemoveHistoryPayload, RemoveHistoryResponse,
+        RemoveRatingsPayload, RemoveRatingsResponse, SyncEpisodeItem, SyncItem, SyncSeason,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChunkLimits {
+    /// Movies, shows and episodes per batch, 0 is taken as 1
+    pub max_items: usize,
+    /// Size of the JSON body. The size of a batch is estimated as the sum of the bodies of its items, a bit more
+    /// than the real body; an item larger than the limit is sent alone.
+    pub max_bytes: usize,
+    /// Split the shows and anime targeting more than `max_items` episodes across batches
+    pub split_shows: bool,
+}
+
+impl Default for ChunkLimits {
+    fn default() -> Self {
+        Self {
+            max_items: 100,
+            max_bytes: 64 * 1024,
+            split_shows: false,
+        }
+    }
+}
+
+impl ChunkLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_items(mut self, max_items: usize) -> Self {
+        self.max_items = max_items.max(1);
+        self
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn with_split_shows(mut self, split_shows: bool) -> Self {
+        self.split_shows = split_shows;
+        self
+    }
+}
+
+/// Payload that can be sent in several batches
+pub trait ChunkedPayload: SimklRequest + Serialize + Default {
+    type Response: Default + DeserializeOwned;
+
+    /// One payload per movie, show, anime or episode, shows weighing more than `max_items` are split
+    fn split(self, max_items: usize) -> Vec<Self>;
+    fn merge(&mut self, other: Self);
+    /// Number of items counted against `ChunkLimits::max_items`
+    fn weight(&self) -> usize;
+    fn merge_response(response: &mut Self::Response, other: Self::Response);
+}
+
+/// Item of a payload section
+trait ChunkItem: Sized {
+    fn weight(&self) -> usize {
+        1
+    }
+
+    fn split(self, _max_items: usize) -> Vec<Self> {
+        vec![self]
+    }
+}
+
+impl ChunkItem for RatedItem {}
+impl ChunkItem for ListItem {}
+impl ChunkItem for SyncEpisodeItem {}
+
+impl ChunkItem for SyncItem {
+    /// Episodes targeted by the item, a whole title or season counts as one
+    fn weight(&self) -> usize {
+        let seasons: usize = self.seasons.iter().map(|s| s.episodes.len().max(1)).sum();
+        (seasons + self.episodes.len()).max(1)
+    }
+
+    /// Ranges of seasons and episodes weighing at most `max_items`, in order
+    fn split(self, max_items: usize) -> Vec<Self> {
+        if ChunkItem::weight(&self) <= max_items {
+            return vec![self];
+        }
+        let empty = SyncItem {
+            seasons: Vec::new(),
+            episodes: Vec::new(),
+            ..self.clone()
+        };
+        let mut pieces = Vec::new();
+        let mut current = empty.clone();
+        let mut push = |current: &mut SyncItem, piece: SyncItem| {
+            if ChunkItem::weight(current) + ChunkItem::weight(&piece) > max_items
+                && !current.is_whole()
+            {
+                pieces.push(std::mem::replace(current, empty.clone()));
+            }
+            current.seasons.extend(piece.seasons);
+            current.episodes.extend(piece.episodes);
+        };
+        for season in self.seasons {
+            if season.episodes.is_empty() {
+                let piece = SyncItem {
+                    seasons: vec![season],
+                    ..empty.clone()
+                };
+                push(&mut current, piece);
+                continue;
+            }
+            for episodes in season.episodes.chunks(max_items) {
+                let piece = SyncItem {
+                    seasons: vec![SyncSeason {
+                        episodes: episodes.to_vec(),
+                        ..season.clone()
+                    }],
+                    ..empty.clone()
+                };
+                push(&mut current, piece);
+            }
+        }
+        for episodes in self.episodes.chunks(max_items) {
+            let piece = SyncItem {
+                episodes: episodes.to_vec(),
+                ..empty.clone()
+            };
+            push(&mut current, piece);
+        }
+        if !current.is_whole() {
+            pieces.push(current);
+        }
+        pieces
+    }
+}
+
+fn split_into<T: ChunkItem, P: Default>(
+    items: Vec<T>,
+    max_items: usize,
+    section: fn(&mut P) -> &mut Vec<T>,
+) -> Vec<P> {
+    items
+        .into_iter()
+        .flat_map(|item| item.split(max_items))
+        .map(|item| {
+            let mut payload = P::default();
+            section(&mut payload).push(item);
+            payload
+        })
+        .collect()
+}
+
+/// Implement `ChunkedPayload` for a payload made of the given sections
+macro_rules! chunked_payload {
+    ($payload:ty, $response:ty, $applied:ident, [$($section:ident),+]) => {
+        impl ChunkedPayload for $payload {
+            type Response = $response;
+
+            fn split(self, max_items: usize) -> Vec<Self> {
+                let mut payloads = Vec::new();
+                $(
+                    let section: fn(&mut Self) -> &mut Vec<_> = |p| &mut p.$section;
+                    payloads.extend(split_into(self.$section, max_items, section));
+                )+
+                payloads
+            }
+
+            fn merge(&mut self, other: Self) {
+                $(self.$section.extend(other.$section);)+
+            }
+
+            fn weight(&self) -> usize {
+                0 $(+ self.$section.iter().map(ChunkItem::weight).sum::<usize>())+
+            }
+
+            fn merge_response(response: &mut Self::Response, other: Self::Response) {
+                response.$applied.merge(other.$applied);
+                response.not_found.merge(other.not_found);
+            }
+        }
+    };
+}
+
+chunked_payload!(
+    HistoryPayload,
+    HistoryResponse,
+    added,
+    [movies, shows, anime, episodes]
+);
+chunked_payload!(
+    RemoveHistoryPayload,
+    RemoveHistoryResponse,
+    deleted,
+    [movies, shows, anime, episodes]
+);
+chunked_payload!(
+    RatingsPayload,
+    RatingsResponse,
+    added,
+    [movies, shows, anime]
+);
+chunked_payload!(
+    RemoveRatingsPayload,
+    RemoveRatingsResponse,
+    deleted,
+    [movies, shows, anime]
+);
+chunked_payload!(
+    AddToListPayload,
+    AddToListResponse,
+    added,
+    [movies, shows, anime]
+);
+
+/// Split a payload into batches respecting `limits`, in the order of the items
+pub fn chunk<P: ChunkedPayload>(payload: P, limits: &ChunkLimits) -> Result<Vec<P>> {
+    payload.validate()?;
+    let max_items = limits.max_items.max(1);
+    let split_above = if limits.split_shows {
+        max_items
+    } else {
+        usize::MAX
+    };
+    let mut chunks = Vec::new();
+    let mut current = P::default();
+    let (mut weight, mut bytes) = (0, 0);
+    for unit in payload.split(split_above) {
+        let unit_weight = unit.weight();
+        // the sum of the bodies is a bit more than the body of the merged payload
+        let unit_bytes = serde_json::to_string(&unit)?.len();
+        if weight > 0 && (weight + unit_weight > max_items || bytes + unit_bytes > limits.max_bytes)
+        {
+            chunks.push(std::mem::take(&mut current));
+            weight = 0;
+            bytes = 0;
+        }
+        current.merge(unit);
+        weight += unit_weight;
+        bytes += unit_bytes;
+    }
+    if weight > 0 {
+        chunks.push(current);
+    }
+    Ok(chunks)
+}
+
+/// Merged result of the batches
+#[derive(Debug, Default)]
+pub struct ChunkReport<P: ChunkedPayload> {
+    pub response: P::Response,
+    /// Number of batches applied
+    pub sent: usize,
+    /// Batches rejected by the server, sending them again would fail the same way
+    pub failed: Vec<(P, SimklError)>,
+    /// Batches not sent because of `error`, to send again later
+    pub unsent: Vec<P>,
+    /// Transport error, authentication error, rate limit or server error that stopped the sending
+    pub error: Option<SimklError>,
+}
+
+impl<P: ChunkedPayload> ChunkReport<P> {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.unsent.is_empty()
+    }
+}
+
+/// The batches after this error would fail the same way until later
+fn stops_sending(error: &SimklError) -> bool {
+    match error {
+        SimklError::Transport(_) => true,
+        SimklError::HttpStatus(status) => matches!(status, 401 | 403 | 429) || *status >= 500,
+        _ => false,
+    }
+}
+
+/// Send a payload in batches, one after the other under the rate limiter. The sending stops at the first transport
+/// error, authentication error, rate limit or server error, the remaining batches are returned in
+/// `ChunkReport::unsent`.
+pub fn send_chunked<P, F>(
+    payload: P,
+    limits: &ChunkLimits,
+    limiter: &Mutex<RateLimiter>,
+    send: F,
+) -> Result<ChunkReport<P>>
+where
+    P: ChunkedPayload,
+    F: Fn(&PreparedRequest) -> Result<SimklResponse>,
+{
+    let mut report = ChunkReport {
+        response: P::Response::default(),
+        sent: 0,
+        failed: Vec::new(),
+        unsent: Vec::new(),
+        error: None,
+    };
+    let mut chunks = chunk(payload, limits)?.into_iter();
+    while let Some(chunk) = chunks.next() {
+        wait_for_slot(limiter);
+        let response = chunk
+            .prepare()
+            .and_then(|request| send(&request))
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json::<P::Response>());
+        match response {
+            Ok(response) => {
+                P::merge_response(&mut report.response, response);
+                report.sent += 1;
+            }
+            Err(e) if stops_sending(&e) => {
+                report.unsent.push(chunk);
+                report.unsent.extend(chunks);
+                report.error = Some(e);
+                break;
+            }
+            Err(e) => report.failed.push((chunk, e)),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MediaIds;
+    use std::{cell::Cell, collections::HashMap, time::Duration};
+
+    #[test]
+    fn test_chunk_history() {
+        let mut payload = HistoryPayload::new()
+            .add_anime(SyncItem::new(MediaIds::new().with_simkl(1)).with_absolute_episodes(1..=30))
+            .add_show(
+                SyncItem::new(MediaIds::new().with_simkl(7))
+                    .with_episodes(1, 1..=3)
+                    .with_episodes(2, 1..=3),
+            );
+        for id in 2..=6 {
+            payload = payload.add_movie(SyncItem::new(MediaIds::new().with_simkl(id)));
+        }
+        // shows are kept whole by default
+        let chunks = chunk(payload.clone(), &ChunkLimits::new().with_max_items(4)).unwrap();
+        assert_eq!(chunks[3].anime[0].weight(), 30);
+        assert_eq!(chunks.len(), 4);
+        let limits = ChunkLimits {
+            max_items: 0,
+            ..Default::default()
+        };
+        assert_eq!(chunk(payload.clone(), &limits).unwrap().len(), 7);
+
+        let limits = ChunkLimits::new().with_max_items(4).with_split_shows(true);
+        let chunks = chunk(payload.clone(), &limits).unwrap();
+        assert!(chunks.iter().all(|c| c.weight() <= limits.max_items));
+        assert_eq!(chunks.iter().map(|c| c.weight()).sum::<usize>(), 41);
+        // every part of the anime keeps its ids
+        let parts: Vec<&SyncItem> = chunks.iter().flat_map(|c| &c.anime).collect();
+        assert_eq!(parts.len(), 8);
+        assert!(parts.iter().all(|p| p.ids.simkl == Some(1)));
+        let shows: Vec<&SyncItem> = chunks.iter().flat_map(|c| &c.shows).collect();
+        assert_eq!(shows.len(), 2);
+        assert_eq!(shows[1].seasons[0].number, 2);
+
+        let calls = Cell::new(0);
+        let limiter = Mutex::new(RateLimiter::new(20, Duration::from_secs(1)));
+        let report = send_chunked(payload, &limits, &limiter, |_| {
+            calls.set(calls.get() + 1);
+            match calls.get() {
+                2 => Ok(SimklResponse::new(400, HashMap::new(), String::new())),
+                4 => Err(SimklError::Transport("timeout".to_string())),
+                _ => {
+                    let body = r#"{"added":{"movies":4},"not_found":{}}"#;
+                    Ok(SimklResponse::new(200, HashMap::new(), body.to_string()))
+                }
+            }
+        })
+        .unwrap();
+        assert_eq!(calls.get(), 4);
+        assert_eq!(report.sent, 2);
+        assert_eq!(report.response.added.total(), 8);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.unsent.len(), chunks.len() - 3);
+        assert!(matches!(report.error, Some(SimklError::Transport(_))));
+
+        // an expired token fails every batch the same way
+        let calls = Cell::new(0);
+        let report = send_chunked(
+            HistoryPayload::new()
+                .add_movie(SyncItem::new(MediaIds::new().with_simkl(2)))
+                .add_movie(SyncItem::new(MediaIds::new().with_simkl(3))),
+            &ChunkLimits::new().with_max_items(1),
+            &limiter,
+            |_| {
+                calls.set(calls.get() + 1);
+                Ok(SimklResponse::new(401, HashMap::new(), String::new()))
+            },
+        )
+        .unwrap();
+        assert_eq!(calls.get(), 1);
+        assert!(report.failed.is_empty());
+        assert_eq!(report.unsent.len(), 2);
+    }
+}
@@ -0,0 +1,579 @@
+//! Curated lists: trending, best of, premieres and airing now
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{
+    anime::AnimeType,
+    calendar::EpisodeItem,
+    error::{Result, SimklError},
+    pagination::PaginationParams,
+    request::SimklRequest,
+    search::{lenient_anime_type, SearchRatings},
+    Genre, MediaIds, MediaType,
+};
+
+/// Section of the API for a media type
+pub(crate) fn media_segment(media_type: MediaType) -> Result<&'static str> {
+    match media_type {
+        MediaType::Movie => Ok("movies"),
+        MediaType::Show => Ok("tv"),
+        MediaType::Anime => Ok("anime"),
+        MediaType::Episode => Err(SimklError::InvalidParameters(
+            "episodes have no discovery lists".to_string(),
+        )),
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TrendingPeriod {
+    Today,
+    Week,
+    Month,
+}
+
+impl TrendingPeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrendingPeriod::Today => "today",
+            TrendingPeriod::Week => "week",
+            TrendingPeriod::Month => "month",
+        }
+    }
+}
+
+/// Ranking used by the best of lists
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BestFilter {
+    All,
+    Year,
+    Month,
+    Voted,
+    Watched,
+}
+
+impl BestFilter {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BestFilter::All => "all",
+            BestFilter::Year => "year",
+            BestFilter::Month => "month",
+            BestFilter::Voted => "voted",
+            BestFilter::Watched => "watched",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PremieresPeriod {
+    /// Premiered recently
+    New,
+    /// Premiering soon
+    Soon,
+}
+
+impl PremieresPeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PremieresPeriod::New => "new",
+            PremieresPeriod::Soon => "soon",
+        }
+    }
+}
+
+/// Day of the airing list
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AiringDate {
+    Today,
+    Tomorrow,
+    Date(chrono::NaiveDate),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DiscoverList {
+    Trending(TrendingPeriod),
+    /// TV shows and anime only
+    Best(BestFilter),
+    /// TV shows and anime only
+    Premieres(PremieresPeriod),
+    /// TV shows and anime only
+    Airing(AiringDate),
+}
+
+/// Get a curated list, the response is a list of `DiscoverItem`.
+///
+/// Examples:
+/// * `GET https://api.simkl.com/tv/trending/week`
+/// * `GET https://api.simkl.com/anime/best/voted`
+/// * `GET https://api.simkl.com/tv/premieres/soon`
+/// * `GET https://api.simkl.com/anime/airing?date=tomorrow`
+#[derive(Debug, Clone)]
+pub struct DiscoverRequest {
+    pub media_type: MediaType,
+    pub list: DiscoverList,
+    pub genre: Option<Genre>,
+    pub extended: Option<String>,
+    pub pagination: PaginationParams,
+}
+
+impl DiscoverRequest {
+    pub fn new(media_type: MediaType, list: DiscoverList) -> Self {
+        Self {
+            media_type,
+            list,
+            genre: None,
+            extended: None,
+            pagination: PaginationParams::new(),
+        }
+    }
+
+    pub fn trending(media_type: MediaType, period: TrendingPeriod) -> Self {
+        Self::new(media_type, DiscoverList::Trending(period))
+    }
+
+    pub fn best(media_type: MediaType, filter: BestFilter) -> Self {
+        Self::new(media_type, DiscoverList::Best(filter))
+    }
+
+    pub fn premieres(media_type: MediaType, period: PremieresPeriod) -> Self {
+        Self::new(media_type, DiscoverList::Premieres(period))
+    }
+
+    pub fn airing(media_type: MediaType, date: AiringDate) -> Self {
+        Self::new(media_type, DiscoverList::Airing(date))
+    }
+
+    pub fn with_genre(mut self, genre: impl Into<Genre>) -> Self {
+        self.genre = Some(genre.into());
+        self
+    }
+
+    pub fn with_extended(mut self, extended: impl Into<String>) -> Self {
+        self.extended = Some(extended.into());
+        self
+    }
+
+    pub fn with_pagination(mut self, pagination: PaginationParams) -> Self {
+        self.pagination = pagination;
+        self
+    }
+}
+
+impl SimklRequest for DiscoverRequest {
+    fn endpoint(&self) -> String {
+        let segment = media_segment(self.media_type).unwrap_or_default();
+        match self.list {
+            DiscoverList::Trending(period) => format!("/{}/trending/{}", segment, period.as_str()),
+            DiscoverList::Best(filter) => format!("/{}/best/{}", segment, filter.as_str()),
+            DiscoverList::Premieres(period) => {
+                format!("/{}/premieres/{}", segment, period.as_str())
+            }
+            DiscoverList::Airing(_) => format!("/{}/airing", segment),
+        }
+    }
+
+    fn query_params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        if let DiscoverList::Airing(date) = self.list {
+            let date = match date {
+                AiringDate::Today => "today".to_string(),
+                AiringDate::Tomorrow => "tomorrow".to_string(),
+                AiringDate::Date(d) => d.format("%Y-%m-%d").to_string(),
+            };
+            params.push(("date".to_string(), date));
+        }
+        if let Some(genre) = self.genre {
+            params.push(("genre".to_string(), genre.as_str().to_string()));
+        }
+        if let Some(ref extended) = self.extended {
+            params.push(("extended".to_string(), extended.clone()));
+        }
+        params.extend(
+            self.pagination
+                .to_query_params()
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v)),
+        );
+        params
+    }
+
+    fn validate(&self) -> Result<()> {
+        media_segment(self.media_type)?;
+        if self.media_type == MediaType::Movie && !matches!(self.list, DiscoverList::Trending(_)) {
+            return Err(SimklError::InvalidParameters(
+                "only trending lists are available for movies".to_string(),
+            ));
+        }
+        if let Some(genre) = self.genre {
+            if genre.media_type() != self.media_type {
+                return Err(SimklError::InvalidParameters(format!(
+                    "genre {} is not a {} genre",
+                    genre.as_str(),
+                    self.media_type
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Release year filter of the genre lists
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GenreYear {
+    ThisYear,
+    Year(u16),
+    /// First year of the decade, example: `2010` for the 2010s
+    Decade(u16),
+}
+
+impl GenreYear {
+    fn to_segment(self) -> String {
+        match self {
+            GenreYear::ThisYear => "this-year".to_string(),
+            GenreYear::Year(y) => y.to_string(),
+            GenreYear::Decade(y) => format!("{}s", y - y % 10),
+        }
+    }
+}
+
+/// Kind of items in the genre lists, each media type has its own
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GenreTypeFilter {
+    // TV
+    TvShows,
+    Entertainment,
+    Documentaries,
+    Animation,
+    // Anime
+    Series,
+    Movies,
+    Ovas,
+    Onas,
+}
+
+impl GenreTypeFilter {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GenreTypeFilter::TvShows => "tv-shows",
+            GenreTypeFilter::Entertainment => "entertainment",
+            GenreTypeFilter::Documentaries => "documentaries",
+            GenreTypeFilter::Animation => "animation-filter",
+            GenreTypeFilter::Series => "series",
+            GenreTypeFilter::Movies => "movies",
+            GenreTypeFilter::Ovas => "ovas",
+            GenreTypeFilter::Onas => "onas",
+        }
+    }
+
+    pub fn media_type(&self) -> MediaType {
+        match self {
+            GenreTypeFilter::TvShows
+            | GenreTypeFilter::Entertainment
+            | GenreTypeFilter::Documentaries
+            | GenreTypeFilter::Animation => MediaType::Show,
+            _ => MediaType::Anime,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GenreSort {
+    PopularToday,
+    PopularThisWeek,
+    PopularThisMonth,
+    Rank,
+    Votes,
+    ReleaseDate,
+    /// TV shows and anime only
+    LastAirDate,
+    /// Movies only
+    Budget,
+    /// Movies only
+    Revenue,
+    MostAnticipated,
+    AToZ,
+    ZToA,
+}
+
+impl GenreSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GenreSort::PopularToday => "popular-today",
+            GenreSort::PopularThisWeek => "popular-this-week",
+            GenreSort::PopularThisMonth => "popular-this-month",
+            GenreSort::Rank => "rank",
+            GenreSort::Votes => "votes",
+            GenreSort::ReleaseDate => "release-date",
+            GenreSort::LastAirDate => "last-air-date",
+            GenreSort::Budget => "budget",
+            GenreSort::Revenue => "revenue",
+            GenreSort::MostAnticipated => "most-anticipated",
+            GenreSort::AToZ => "a-z",
+            GenreSort::ZToA => "z-a",
+        }
+    }
+}
+
+/// Browse a genre, the response is a list of `DiscoverItem`. Filters that are not set are replaced by `all`.
+///
+/// Paths by media type:
+/// * `GET https://api.simkl.com/tv/genres/{genre}/{type}/{country}/{network}/{year}/{sort}`
+/// * `GET https://api.simkl.com/anime/genres/{genre}/{type}/{network}/{year}/{sort}`
+/// * `GET https://api.simkl.com/movies/genres/{genre}/{type}/{country}/{year}/{sort}`
+#[derive(Debug, Clone)]
+pub struct GenreBrowseRequest {
+    pub media_type: MediaType,
+    pub genre: Option<Genre>,
+    pub r#type: Option<GenreTypeFilter>,
+    /// Two letters country code, example: `us`. Not available for anime.
+    pub country: Option<String>,
+    /// Example: `netflix`. Not available for movies.
+    pub network: Option<String>,
+    pub year: Option<GenreYear>,
+    pub sort: Option<GenreSort>,
+    pub pagination: PaginationParams,
+}
+
+impl GenreBrowseRequest {
+    pub fn new(media_type: MediaType) -> Self {
+        Self {
+            media_type,
+            genre: None,
+            r#type: None,
+            country: None,
+            network: None,
+            year: None,
+            sort: None,
+            pagination: PaginationParams::new(),
+        }
+    }
+
+    pub fn with_genre(mut self, genre: impl Into<Genre>) -> Self {
+        self.genre = Some(genre.into());
+        self
+    }
+
+    pub fn with_type(mut self, r#type: GenreTypeFilter) -> Self {
+        self.r#type = Some(r#type);
+        self
+    }
+
+    pub fn with_country(mut self, country: impl Into<String>) -> Self {
+        self.country = Some(country.into().to_lowercase());
+        self
+    }
+
+    pub fn with_network(mut self, network: impl Into<String>) -> Self {
+        self.network = Some(network.into().to_lowercase());
+        self
+    }
+
+    pub fn with_year(mut self, year: GenreYear) -> Self {
+        self.year = Some(year);
+        self
+    }
+
+    pub fn with_sort(mut self, sort: GenreSort) -> Self {
+        self.sort = Some(sort);
+        self
+    }
+
+    pub fn with_pagination(mut self, pagination: PaginationParams) -> Self {
+        self.pagination = pagination;
+        self
+    }
+}
+
+impl SimklRequest for GenreBrowseRequest {
+    fn endpoint(&self) -> String {
+        let mut segments: Vec<String> = vec![
+            media_segment(self.media_type)
+                .unwrap_or_default()
+                .to_string(),
+            "genres".to_string(),
+            self.genre.map_or("all", |g| g.as_str()).to_string(),
+            self.r#type.map_or("all", |t| t.as_str()).to_string(),
+        ];
+        if self.media_type != MediaType::Anime {
+            segments.push(self.country.clone().unwrap_or_else(|| "all".to_string()));
+        }
+        if self.media_type != MediaType::Movie {
+            segments.push(self.network.clone().unwrap_or_else(|| "all".to_string()));
+        }
+        segments.push(
+            self.year
+                .map_or_else(|| "all".to_string(), |y| y.to_segment()),
+        );
+        if let Some(sort) = self.sort {
+            segments.push(sort.as_str().to_string());
+        }
+        format!("/{}", segments.join("/"))
+    }
+
+    fn query_params(&self) -> Vec<(String, String)> {
+        self.pagination
+            .to_query_params()
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect()
+    }
+
+    fn validate(&self) -> Result<()> {
+        media_segment(self.media_type)?;
+        if let Some(genre) = self.genre {
+            if genre.media_type() != self.media_type {
+                return Err(SimklError::InvalidParameters(format!(
+                    "genre {} is not a {} genre",
+                    genre.as_str(),
+                    self.media_type
+                )));
+            }
+        }
+        if let Some(t) = self.r#type {
+            if t.media_type() != self.media_type {
+                return Err(SimklError::InvalidParameters(format!(
+                    "type {} is not available for {}",
+                    t.as_str(),
+                    self.media_type
+                )));
+            }
+        }
+        match (self.media_type, &self.country, &self.network) {
+            (MediaType::Anime, Some(_), _) => {
+                return Err(SimklError::InvalidParameters(
+                    "anime cannot be filtered by country".to_string(),
+                ))
+            }
+            (MediaType::Movie, _, Some(_)) => {
+                return Err(SimklError::InvalidParameters(
+                    "movies cannot be filtered by network".to_string(),
+                ))
+            }
+            _ => {}
+        }
+        if let Some(ref country) = self.country {
+            if country.len() != 2 || !country.chars().all(|c| c.is_ascii_lowercase()) {
+                return Err(SimklError::InvalidParameters(format!(
+                    "invalid country code: {}",
+                    country
+                )));
+            }
+        }
+        if let Some(ref network) = self.network {
+            if network.is_empty() || network.contains('/') {
+                return Err(SimklError::InvalidParameters(format!(
+                    "invalid network: {}",
+                    network
+                )));
+            }
+        }
+        match (self.media_type, self.sort) {
+            (MediaType::Movie, Some(GenreSort::LastAirDate)) => Err(SimklError::InvalidParameters(
+                "movies cannot be sorted by last air date".to_string(),
+            )),
+            (MediaType::Show | MediaType::Anime, Some(GenreSort::Budget | GenreSort::Revenue)) => {
+                Err(SimklError::InvalidParameters(
+                    "only movies can be sorted by budget or revenue".to_string(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Item of a discovery or genre list
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct DiscoverItem {
+    pub title: String,
+    pub year: Option<u16>,
+    /// Relative SIMKL URL, example: `/tv/17465/game-of-thrones`
+    pub url: Option<String>,
+    /// Poster reference, use it with `images::get_poster_url`
+    pub poster: Option<String>,
+    /// Fanart reference, use it with `images::get_fanart_url`
+    pub fanart: Option<String>,
+    #[serde(default)]
+    pub ids: MediaIds,
+    pub rank: Option<u32>,
+    pub ratings: Option<SearchRatings>,
+    /// Air date of the episode for airing lists
+    pub date: Option<DateTime<Utc>>,
+    pub release_date: Option<String>,
+    /// Next episode for airing lists
+    pub episode: Option<EpisodeItem>,
+    #[serde(default, rename = "type", deserialize_with = "lenient_anime_type")]
+    pub anime_type: Option<AnimeType>,
+    pub overview: Option<String>,
+    /// Number of users who watched it during the period, for trending lists
+    pub watched: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{movie::MovieGenre, show::TvGenre};
+
+    #[test]
+    fn test_discover_urls() {
+        let request = DiscoverRequest::trending(MediaType::Movie, TrendingPeriod::Week)
+            .with_pagination(PaginationParams::new().page(2).limit(20));
+        assert!(request.validate().is_ok());
+        assert_eq!(
+            request.build_url(),
+            "https://api.simkl.com/movies/trending/week?page=2&limit=20"
+        );
+
+        let request = DiscoverRequest::airing(MediaType::Anime, AiringDate::Tomorrow);
+        assert_eq!(
+            request.build_url(),
+            "https://api.simkl.com/anime/airing?date=tomorrow"
+        );
+
+        let request = DiscoverRequest::best(MediaType::Show, BestFilter::Voted)
+            .with_genre(TvGenre::science_fiction);
+        assert!(request.validate().is_ok());
+        assert_eq!(
+            request.build_url(),
+            "https://api.simkl.com/tv/best/voted?genre=science-fiction"
+        );
+        assert!(request.with_genre(MovieGenre::action).validate().is_err());
+        assert!(
+            DiscoverRequest::premieres(MediaType::Movie, PremieresPeriod::New)
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_genre_browse_urls() {
+        let request = GenreBrowseRequest::new(MediaType::Show)
+            .with_genre(TvGenre::drama)
+            .with_network("Netflix")
+            .with_year(GenreYear::Decade(2015))
+            .with_sort(GenreSort::Rank)
+            .with_pagination(PaginationParams::new().page(3));
+        assert!(request.validate().is_ok());
+        assert_eq!(
+            request.build_url(),
+            "https://api.simkl.com/tv/genres/drama/all/all/netflix/2010s/rank?page=3"
+        );
+
+        let request = GenreBrowseRequest::new(MediaType::Movie)
+            .with_country("US")
+            .with_sort(GenreSort::Revenue);
+        assert!(request.validate().is_ok());
+        assert_eq!(
+            request.build_url(),
+            "https://api.simkl.com/movies/genres/all/all/us/all/revenue"
+        );
+        assert!(request.with_network("netflix").validate().is_err());
+
+        let request = GenreBrowseRequest::new(MediaType::Anime).with_type(GenreTypeFilter::Ovas);
+        assert_eq!(
+            request.build_url(),
+            "https://api.simkl.com/anime/genres/all/ovas/all/all"
+        );
+        assert!(request.clone().with_country("jp").validate().is_err());
+        assert!(request.with_sort(GenreSort::Budget).validate().is_err());
+    }
+}
@@ -3,6 +3,7 @@ use std::fmt;
 pub type Result<T> = std::result::Result<T, SimklError>;
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum SimklError {
     /// Erreur de sérialisation/désérialisation JSON
     Json(serde_json::Error),
@@ -12,6 +13,17 @@ pub enum SimklError {
     InvalidParameters(String),
     /// Erreur de parsing de la réponse
     ParseError(String),
+    /// Code HTTP d'erreur renvoyé par l'API
+    HttpStatus(u16),
+    /// Erreur du client HTTP utilisé pour envoyer la requête
+    Transport(String),
+    /// Erreur de lecture ou d'écriture d'un fichier
+    Io(std::io::Error),
+    /// Un visionnage est déjà en cours (`409 Conflict`), avec sa date d'expiration
+    AlreadyCheckedIn(Option<chrono::DateTime<chrono::Utc>>),
+    /// Erreur de la base SQLite
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
 }
 
 impl fmt::Display for SimklError {
@@ -21,6 +33,15 @@ impl fmt::Display for SimklError {
             SimklError::InvalidUrl(err) => write!(f, "URL invalide: {}", err),
             SimklError::InvalidParameters(msg) => write!(f, "Paramètres invalides: {}", msg),
             SimklError::ParseError(msg) => write!(f, "Erreur de parsing: {}", msg),
+            SimklError::HttpStatus(code) => write!(f, "Erreur HTTP: {}", code),
+            SimklError::Transport(msg) => write!(f, "Erreur de transport: {}", msg),
+            SimklError::Io(err) => write!(f, "Erreur d'entrée/sortie: {}", err),
+            SimklError::AlreadyCheckedIn(Some(expires_at)) => {
+                write!(f, "Visionnage déjà en cours jusqu'à {}", expires_at)
+            }
+            SimklError::AlreadyCheckedIn(None) => write!(f, "Visionnage déjà en cours"),
+            #[cfg(feature = "sqlite")]
+            SimklError::Sqlite(err) => write!(f, "Erreur SQLite: {}", err),
         }
     }
 }
@@ -38,3 +59,16 @@ impl From<url::ParseError> for SimklError {
         SimklError::InvalidUrl(err)
     }
 }
+
+impl From<std::io::Error> for SimklError {
+    fn from(err: std::io::Error) -> Self {
+        SimklError::Io(err)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for SimklError {
+    fn from(err: rusqlite::Error) -> Self {
+        SimklError::Sqlite(err)
+    }
+}
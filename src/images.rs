@@ -248,7 +248,7 @@ mod tests {
                 EpisodeSize::M112x63,
                 true
             ),
-            "https://wsrv.nl/?url=https://simkl.in/episodes/26/265319260301d2ee2_s.webp"
+            "https://wsrv.nl/?url=https://simkl.in/episodes/26/265319260301d2ee2_m.webp"
         );
     }
 
@@ -270,11 +270,11 @@ mod tests {
         );
         assert_eq!(
             get_avatar_url(prefix.clone(), image_url.clone(), AvatarSize::Big, true),
-            "https://wsrv.nl/?url=https://simkl.in/episodes/1/1_200.webp"
+            "https://wsrv.nl/?url=https://simkl.in/episodes/1/1_256.webp"
         );
         assert_eq!(
             get_avatar_url(prefix.clone(), image_url.clone(), AvatarSize::VeryBig, true),
-            "https://wsrv.nl/?url=https://simkl.in/episodes/1/1_256.webp"
+            "https://wsrv.nl/?url=https://simkl.in/episodes/1/1_512.webp"
         );
     }
 }
@@ -2,20 +2,33 @@ use std::{fmt, str::FromStr};
 
 use serde::{Deserialize, Serialize};
 
+use crate::{anime::AnimeGenre, movie::MovieGenre, show::TvGenre};
+
 pub mod anime;
 pub mod auth;
 pub mod calendar;
+pub mod chunk;
+pub mod discover;
 pub mod error;
 pub mod images;
+pub mod library;
 pub mod movie;
+pub mod outbox;
 pub mod pagination;
 pub mod pin;
+pub mod planner;
+pub mod preview;
 pub mod rate_limit;
+pub mod ratings;
 pub mod request;
 pub mod response;
+pub mod scrobble;
 pub mod search;
 pub mod show;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 pub mod sync;
+pub mod undo;
 pub mod user;
 
 #[derive(Debug)]
@@ -95,11 +108,12 @@ impl Default for Extended {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 #[repr(u8)]
 pub enum MediaType {
     Movie,
+    #[serde(alias = "tv")]
     Show,
     Anime,
     Episode,
@@ -142,6 +156,106 @@ impl MediaType {
     }
 }
 
+/// List of the user library an item belongs to
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchStatus {
+    Watching,
+    PlanToWatch,
+    Hold,
+    Completed,
+    Dropped,
+    NotInteresting,
+}
+
+impl fmt::Display for WatchStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for WatchStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "watching" => Ok(WatchStatus::Watching),
+            "plantowatch" => Ok(WatchStatus::PlanToWatch),
+            "hold" => Ok(WatchStatus::Hold),
+            "completed" => Ok(WatchStatus::Completed),
+            "dropped" => Ok(WatchStatus::Dropped),
+            "notinteresting" => Ok(WatchStatus::NotInteresting),
+            _ => Err(()),
+        }
+    }
+}
+
+impl WatchStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchStatus::Watching => "watching",
+            WatchStatus::PlanToWatch => "plantowatch",
+            WatchStatus::Hold => "hold",
+            WatchStatus::Completed => "completed",
+            WatchStatus::Dropped => "dropped",
+            WatchStatus::NotInteresting => "notinteresting",
+        }
+    }
+
+    /// Movies cannot be watching or on hold
+    pub fn applies_to(&self, media_type: MediaType) -> bool {
+        match media_type {
+            MediaType::Movie => !matches!(self, WatchStatus::Watching | WatchStatus::Hold),
+            MediaType::Show | MediaType::Anime => true,
+            MediaType::Episode => false,
+        }
+    }
+}
+
+/// A genre, tied to the kind of media it applies to since SIMKL has a distinct genre list for each of them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Genre {
+    Movie(MovieGenre),
+    Tv(TvGenre),
+    Anime(AnimeGenre),
+}
+
+impl Genre {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Genre::Movie(g) => g.as_str(),
+            Genre::Tv(g) => g.as_str(),
+            Genre::Anime(g) => g.as_str(),
+        }
+    }
+
+    pub fn media_type(&self) -> MediaType {
+        match self {
+            Genre::Movie(_) => MediaType::Movie,
+            Genre::Tv(_) => MediaType::Show,
+            Genre::Anime(_) => MediaType::Anime,
+        }
+    }
+}
+
+impl From<MovieGenre> for Genre {
+    fn from(genre: MovieGenre) -> Self {
+        Genre::Movie(genre)
+    }
+}
+
+impl From<TvGenre> for Genre {
+    fn from(genre: TvGenre) -> Self {
+        Genre::Tv(genre)
+    }
+}
+
+impl From<AnimeGenre> for Genre {
+    fn from(genre: AnimeGenre) -> Self {
+        Genre::Anime(genre)
+    }
+}
+
 pub fn get_extended_parameter(extended: Extended) -> Result<String, &'static str> {
     if extended.full
         && (extended.title
@@ -204,18 +318,75 @@ pub fn get_pagination_parameter(page: u16, limit: u16) -> String {
     result
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Default)]
+/// Numeric ids are sometimes sent as strings by the API (e.g. `"tmdb": "245891"`)
+fn lenient_id<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        Number(u32),
+        Text(String),
+    }
+    match Option::<Id>::deserialize(deserializer)? {
+        Some(Id::Number(id)) => Ok(Some(id)),
+        Some(Id::Text(id)) if id.is_empty() => Ok(None),
+        Some(Id::Text(id)) => id.parse().map(Some).map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize, Default)]
 pub struct MediaIds {
+    #[serde(
+        default,
+        alias = "simkl_id",
+        deserialize_with = "lenient_id",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub simkl: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub slug: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub imdb: Option<String>,
     // TV only
+    #[serde(
+        default,
+        deserialize_with = "lenient_id",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub tmdb: Option<u32>,
     // anime only
+    #[serde(
+        default,
+        deserialize_with = "lenient_id",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub mal: Option<u32>,
+    #[serde(
+        default,
+        deserialize_with = "lenient_id",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub anilist: Option<u32>,
+    #[serde(
+        default,
+        deserialize_with = "lenient_id",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub anidb: Option<u32>,
+    #[serde(
+        default,
+        deserialize_with = "lenient_id",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub tvdb: Option<u32>,
+    #[serde(
+        default,
+        deserialize_with = "lenient_id",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub kitsu: Option<u32>,
 }
 
@@ -310,7 +481,7 @@ pub struct Episode {
 pub fn get_auth_url(client_id: &str, redirect_url: &str) -> String {
     let mut result = String::with_capacity(128);
     result.push_str(API_URL);
-    result.push_str("oauth/authorize?response_type=code&redirect_uri=");
+    result.push_str("/oauth/authorize?response_type=code&redirect_uri=");
     result.push_str(redirect_url);
     result.push_str("&client_id=");
     result.push_str(client_id);
@@ -319,24 +490,54 @@ pub fn get_auth_url(client_id: &str, redirect_url: &str) -> String {
 
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
 pub struct Rank {
-    r#type: String,
-    votes: u32,
+    pub r#type: Option<String>,
+    pub votes: Option<u32>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
 pub struct Rating {
     pub rating: Option<f32>,
     pub votes: Option<u32>,
+    /// Only given by MAL
+    pub rank: Option<u32>,
 }
 
+/// Drop rate is sent either as a number or as a percentage string (`"2.3%"`)
+fn lenient_percentage<'de, D>(deserializer: D) -> Result<Option<f32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Percentage {
+        Number(f32),
+        Text(String),
+    }
+    match Option::<Percentage>::deserialize(deserializer)? {
+        Some(Percentage::Number(p)) => Ok(Some(p)),
+        Some(Percentage::Text(p)) => Ok(p.trim().trim_end_matches('%').parse().ok()),
+        None => Ok(None),
+    }
+}
+
+/// Aggregated ratings returned by `ratings::RatingsRequest`, fields are only set when requested
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
 pub struct Ratings {
-    id: u16,
-    link: String,
-    rank: Rank,
-    simkl: Rating,
-    imdb: Option<Rating>,
-    has_trailer: bool,
+    /// SIMKL id
+    pub id: u32,
+    /// SIMKL page of the item
+    pub link: Option<String>,
+    pub rank: Option<Rank>,
+    pub simkl: Option<Rating>,
+    pub imdb: Option<Rating>,
+    pub mal: Option<Rating>,
+    /// Percentage of users who dropped it
+    #[serde(default, deserialize_with = "lenient_percentage")]
+    pub droprate: Option<f32>,
+    /// Number of users per reaction
+    #[serde(default)]
+    pub reactions: std::collections::HashMap<String, u32>,
+    pub has_trailer: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -345,10 +546,3 @@ pub struct AirInfo {
     pub time: Option<String>,
     pub timezone: Option<String>,
 }
-
-/// Get rating (token not required)
-pub fn get_rating() -> Rating {
-    todo!()
-}
-
-// TODO: find random
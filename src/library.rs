@@ -0,0 +1,512 @@
+//! Local copy of the user library, updated with the responses of `/sync/all-items`.
+//!
+//! Items are keyed by their `MediaIds` reduced to the SIMKL id when known, so the same media found through different
+//! ids is stored once. Their other ids (IMDb, TMDb, TVDB, MAL, ...) are indexed, so an item can be looked up by any of
+//! them, and an item first stored without SIMKL id is merged with the same media received later with it. Removed items are never reported by `date_from` deltas: they are found by comparing the
+//! sections with the id lists returned by `planner::removals_request`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Result,
+    planner::{plan_sync, SyncPlan},
+    sync::{Activities, AllItems, AllItemsRequest, LibraryItem},
+    MediaIds, MediaType, WatchStatus,
+};
+
+/// Key used to store a media: the SIMKL id alone when present, every known id otherwise
+pub fn library_key(ids: &MediaIds) -> MediaIds {
+    match ids.simkl {
+        Some(simkl) => MediaIds::new().with_simkl(simkl),
+        None => ids.clone(),
+    }
+}
+
+fn item_key(item: &LibraryItem) -> Option<MediaIds> {
+    item.ids()
+        .filter(|ids| **ids != MediaIds::default())
+        .map(library_key)
+}
+
+/// Id of a media in another database than SIMKL
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum AltId {
+    Imdb(String),
+    Tmdb(u32),
+    Mal(u32),
+    Anilist(u32),
+    Anidb(u32),
+    Tvdb(u32),
+    Kitsu(u32),
+}
+
+fn alt_ids(ids: &MediaIds) -> Vec<AltId> {
+    let numbers = [
+        ids.tmdb.map(AltId::Tmdb),
+        ids.mal.map(AltId::Mal),
+        ids.anilist.map(AltId::Anilist),
+        ids.anidb.map(AltId::Anidb),
+        ids.tvdb.map(AltId::Tvdb),
+        ids.kitsu.map(AltId::Kitsu),
+    ];
+    ids.imdb
+        .clone()
+        .map(AltId::Imdb)
+        .into_iter()
+        .chain(numbers.into_iter().flatten())
+        .collect()
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Library {
+    shows: HashMap<MediaIds, LibraryItem>,
+    anime: HashMap<MediaIds, LibraryItem>,
+    movies: HashMap<MediaIds, LibraryItem>,
+    /// Activities of the last synchronization, compared with fresh ones to plan the next one
+    pub activities: Option<Activities>,
+    /// Key of the items by their other ids
+    aliases: HashMap<(MediaType, AltId), MediaIds>,
+}
+
+impl From<AllItems> for Library {
+    fn from(items: AllItems) -> Self {
+        let mut library = Library::new();
+        library.apply_delta(items);
+        library
+    }
+}
+
+/// On disk format, the maps are stored as lists since JSON keys are strings
+#[derive(Serialize)]
+struct LibraryFileRef<'a> {
+    activities: &'a Option<Activities>,
+    shows: Vec<&'a LibraryItem>,
+    anime: Vec<&'a LibraryItem>,
+    movies: Vec<&'a LibraryItem>,
+}
+
+#[derive(Deserialize)]
+struct LibraryFile {
+    #[serde(default)]
+    activities: Option<Activities>,
+    #[serde(flatten)]
+    items: AllItems,
+}
+
+impl Library {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Items of a section by key, they are changed through `insert` and `remove` which keep their ids indexed
+    pub fn section(&self, media_type: MediaType) -> Option<&HashMap<MediaIds, LibraryItem>> {
+        match media_type {
+            MediaType::Show => Some(&self.shows),
+            MediaType::Anime => Some(&self.anime),
+            MediaType::Movie => Some(&self.movies),
+            MediaType::Episode => None,
+        }
+    }
+
+    fn section_mut(
+        &mut self,
+        media_type: MediaType,
+    ) -> Option<&mut HashMap<MediaIds, LibraryItem>> {
+        match media_type {
+            MediaType::Show => Some(&mut self.shows),
+            MediaType::Anime => Some(&mut self.anime),
+            MediaType::Movie => Some(&mut self.movies),
+            MediaType::Episode => None,
+        }
+    }
+
+    /// Item matching any of `ids`
+    pub fn get(&self, media_type: MediaType, ids: &MediaIds) -> Option<&LibraryItem> {
+        let section = self.section(media_type)?;
+        section.get(&library_key(ids)).or_else(|| {
+            alt_ids(ids).into_iter().find_map(|id| {
+                let key = self.aliases.get(&(media_type, id))?;
+                section.get(key)
+            })
+        })
+    }
+
+    /// Items of every section
+    pub fn iter(&self) -> impl Iterator<Item = (MediaType, &LibraryItem)> {
+        [MediaType::Show, MediaType::Anime, MediaType::Movie]
+            .into_iter()
+            .flat_map(move |t| {
+                self.section(t)
+                    .into_iter()
+                    .flat_map(move |s| s.values().map(move |i| (t, i)))
+            })
+    }
+
+    /// Look for an item in every section
+    pub fn find(&self, ids: &MediaIds) -> Option<(MediaType, &LibraryItem)> {
+        [MediaType::Show, MediaType::Anime, MediaType::Movie]
+            .into_iter()
+            .find_map(|t| self.get(t, ids).map(|item| (t, item)))
+    }
+
+    /// Add or replace an item, ignored when it has no id. An item stored under another key but sharing one of its ids
+    /// is the same media when one of them has no SIMKL id: they are merged under the SIMKL id.
+    pub fn insert(&mut self, media_type: MediaType, mut item: LibraryItem) {
+        let Some(mut key) = item_key(&item) else {
+            return;
+        };
+        if self.section(media_type).is_none() {
+            return;
+        }
+        if let Some(previous) = self.alias_hit(media_type, &item, &key) {
+            match (previous.simkl, key.simkl) {
+                // two media sharing an id
+                (Some(_), Some(_)) => {}
+                (Some(simkl), None) => {
+                    if let Some(ids) = ids_mut(&mut item) {
+                        ids.simkl = Some(simkl);
+                    }
+                    key = previous;
+                }
+                (None, _) => {
+                    self.remove(media_type, &previous);
+                }
+            }
+        }
+        self.unindex(media_type, &key);
+        if let Some(ids) = item.ids() {
+            for id in alt_ids(ids) {
+                self.aliases.insert((media_type, id), key.clone());
+            }
+        }
+        if let Some(section) = self.section_mut(media_type) {
+            section.insert(key, item);
+        }
+    }
+
+    /// Remove the item stored under `key`
+    pub fn remove(&mut self, media_type: MediaType, key: &MediaIds) -> Option<LibraryItem> {
+        self.unindex(media_type, key);
+        self.section_mut(media_type)?.remove(key)
+    }
+
+    /// Key of another item sharing one of the ids of `item`
+    fn alias_hit(
+        &self,
+        media_type: MediaType,
+        item: &LibraryItem,
+        key: &MediaIds,
+    ) -> Option<MediaIds> {
+        alt_ids(item.ids()?)
+            .into_iter()
+            .filter_map(|id| self.aliases.get(&(media_type, id)))
+            .find(|k| *k != key)
+            .cloned()
+    }
+
+    fn unindex(&mut self, media_type: MediaType, key: &MediaIds) {
+        let Some(ids) = self
+            .section(media_type)
+            .and_then(|s| s.get(key))
+            .and_then(|item| item.ids())
+        else {
+            return;
+        };
+        for id in alt_ids(ids) {
+            let entry = (media_type, id);
+            if self.aliases.get(&entry) == Some(key) {
+                self.aliases.remove(&entry);
+            }
+        }
+    }
+
+    /// Items of a section having the given status
+    pub fn with_status(
+        &self,
+        media_type: MediaType,
+        status: WatchStatus,
+    ) -> impl Iterator<Item = &LibraryItem> {
+        self.section(media_type)
+            .into_iter()
+            .flat_map(|s| s.values())
+            .filter(move |item| item.status == Some(status))
+    }
+
+    pub fn len(&self) -> usize {
+        self.shows.len() + self.anime.len() + self.movies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add or update items, used for `date_from` deltas
+    pub fn apply_delta(&mut self, items: AllItems) {
+        for (media_type, list) in sections(items) {
+            for item in list {
+                self.insert(media_type, item);
+            }
+        }
+    }
+
+    /// Replace a whole section, or only the items having `status` when set
+    pub fn replace(
+        &mut self,
+        media_type: MediaType,
+        status: Option<WatchStatus>,
+        items: Vec<LibraryItem>,
+    ) {
+        let Some(section) = self.section(media_type) else {
+            return;
+        };
+        let replaced: Vec<MediaIds> = section
+            .iter()
+            .filter(|(_, item)| status.is_none() || item.status == status)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in replaced {
+            self.remove(media_type, &key);
+        }
+        for item in items {
+            self.insert(media_type, item);
+        }
+    }
+
+    /// Drop the items of a section missing from `present`, the response of `planner::removals_request`.
+    /// Returns the removed items.
+    pub fn apply_removals(
+        &mut self,
+        media_type: MediaType,
+        present: &[LibraryItem],
+    ) -> Vec<LibraryItem> {
+        let keys: HashSet<MediaIds> = present.iter().filter_map(item_key).collect();
+        let Some(section) = self.section(media_type) else {
+            return Vec::new();
+        };
+        let removed: Vec<MediaIds> = section
+            .keys()
+            .filter(|key| !keys.contains(*key))
+            .cloned()
+            .collect();
+        removed
+            .iter()
+            .filter_map(|key| self.remove(media_type, key))
+            .collect()
+    }
+
+    /// Apply the response of a request built by the planner: removals for id lists, delta for `date_from`, full
+    /// replacement otherwise
+    pub fn apply(&mut self, request: &AllItemsRequest, items: AllItems) {
+        let ids_only = request.extended.as_deref() == Some("simkl_ids_only");
+        if request.date_from.is_some() && !ids_only {
+            self.apply_delta(items);
+            return;
+        }
+        let requested: Vec<MediaType> = match request.media_type {
+            Some(media_type) => vec![media_type],
+            None => vec![MediaType::Show, MediaType::Anime, MediaType::Movie],
+        };
+        let mut sections: HashMap<MediaType, Vec<LibraryItem>> =
+            sections(items).into_iter().collect();
+        for media_type in requested {
+            let list = sections.remove(&media_type).unwrap_or_default();
+            if ids_only {
+                self.apply_removals(media_type, &list);
+            } else {
+                self.replace(media_type, request.status, list);
+            }
+        }
+    }
+
+    /// Compare fresh activities with the ones of the last synchronization, see `record_activities`
+    pub fn plan(&self, current: &Activities) -> SyncPlan {
+        plan_sync(current, self.activities.as_ref())
+    }
+
+    /// The library mirrors the server since a completed sync: a media missing from it is not in the user library
+    pub fn is_synced(&self) -> bool {
+        self.activities.is_some()
+    }
+
+    /// Store the activities once every call of the plan was applied
+    pub fn record_activities(&mut self, activities: Activities) {
+        self.activities = Some(activities);
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let file = LibraryFileRef {
+            activities: &self.activities,
+            shows: self.shows.values().collect(),
+            anime: self.anime.values().collect(),
+            movies: self.movies.values().collect(),
+        };
+        Ok(serde_json::to_string(&file)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let file: LibraryFile = serde_json::from_str(json)?;
+        let mut library = Library::from(file.items);
+        library.activities = file.activities;
+        Ok(library)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_file(path.as_ref(), &self.to_json()?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+}
+
+/// Write through a temporary file so a crash never leaves a truncated file
+pub(crate) fn write_file(path: &Path, contents: &str) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn ids_mut(item: &mut LibraryItem) -> Option<&mut MediaIds> {
+    item.show
+        .as_mut()
+        .or(item.movie.as_mut())
+        .map(|m| &mut m.ids)
+}
+
+fn sections(items: AllItems) -> [(MediaType, Vec<LibraryItem>); 3] {
+    [
+        (MediaType::Show, items.shows),
+        (MediaType::Anime, items.anime),
+        (MediaType::Movie, items.movies),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::LibraryMedia;
+    use chrono::{TimeZone, Utc};
+
+    fn item(ids: MediaIds, status: WatchStatus) -> LibraryItem {
+        LibraryItem {
+            status: Some(status),
+            show: Some(LibraryMedia {
+                ids,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_library() {
+        let mut library = Library::new();
+        library.apply(
+            &AllItemsRequest::new().with_type(MediaType::Show),
+            AllItems {
+                shows: vec![
+                    item(MediaIds::new().with_simkl(1), WatchStatus::Watching),
+                    item(MediaIds::new().with_simkl(2), WatchStatus::PlanToWatch),
+                    item(MediaIds::new().with_simkl(3), WatchStatus::Completed),
+                ],
+                ..Default::default()
+            },
+        );
+        assert_eq!(library.len(), 3);
+
+        let date = Utc.with_ymd_and_hms(2024, 3, 1, 20, 0, 0).unwrap();
+        library.apply(
+            &AllItemsRequest::new()
+                .with_type(MediaType::Show)
+                .with_date_from(date),
+            AllItems {
+                shows: vec![item(
+                    MediaIds::new()
+                        .with_simkl(2)
+                        .with_imdb(String::from("tt0903747")),
+                    WatchStatus::Watching,
+                )],
+                ..Default::default()
+            },
+        );
+        assert_eq!(library.len(), 3);
+        assert_eq!(
+            library
+                .with_status(MediaType::Show, WatchStatus::Watching)
+                .count(),
+            2
+        );
+        assert!(library.find(&MediaIds::new().with_simkl(2)).is_some());
+        let imdb = MediaIds::new().with_imdb(String::from("tt0903747"));
+        assert_eq!(
+            library.find(&imdb).and_then(|(_, item)| item.ids()?.simkl),
+            Some(2)
+        );
+        assert!(library.get(MediaType::Movie, &imdb).is_none());
+
+        library.apply(
+            &crate::planner::removals_request(MediaType::Show),
+            AllItems {
+                shows: vec![
+                    item(MediaIds::new().with_simkl(1), WatchStatus::Watching),
+                    item(MediaIds::new().with_simkl(2), WatchStatus::Watching),
+                ],
+                ..Default::default()
+            },
+        );
+        assert_eq!(library.len(), 2);
+        assert!(library
+            .get(MediaType::Show, &MediaIds::new().with_simkl(3))
+            .is_none());
+        library.replace(MediaType::Show, Some(WatchStatus::Watching), Vec::new());
+        assert!(library.is_empty());
+        assert!(library.find(&imdb).is_none());
+
+        library.record_activities(Activities::default());
+        let restored = Library::from_json(&library.to_json().unwrap()).unwrap();
+        assert_eq!(restored, library);
+    }
+
+    #[test]
+    fn test_merge_on_alias() {
+        let imdb = || MediaIds::new().with_imdb(String::from("tt0903747"));
+        let mut library = Library::new();
+        library.insert(MediaType::Show, item(imdb(), WatchStatus::PlanToWatch));
+        library.insert(
+            MediaType::Show,
+            item(imdb().with_simkl(2), WatchStatus::Watching),
+        );
+        assert_eq!(library.len(), 1);
+        assert!(library
+            .section(MediaType::Show)
+            .unwrap()
+            .contains_key(&MediaIds::new().with_simkl(2)));
+
+        // received again without its SIMKL id
+        library.insert(
+            MediaType::Show,
+            item(imdb().with_tmdb(1396), WatchStatus::Completed),
+        );
+        assert_eq!(library.len(), 1);
+        let stored = library
+            .get(MediaType::Show, &MediaIds::new().with_tmdb(1396))
+            .unwrap();
+        assert_eq!(stored.status, Some(WatchStatus::Completed));
+        assert_eq!(stored.ids().unwrap().simkl, Some(2));
+
+        // another media with the same IMDb id
+        library.insert(
+            MediaType::Show,
+            item(imdb().with_simkl(3), WatchStatus::Watching),
+        );
+        assert_eq!(library.len(), 2);
+        assert_eq!(library.iter().count(), 2);
+    }
+}
@@ -24,3 +24,32 @@ pub enum MovieGenre {
     war,
     western,
 }
+
+impl MovieGenre {
+    /// Slug used by the API in URLs (e.g. genre filters)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MovieGenre::action => "action",
+            MovieGenre::adventure => "adventure",
+            MovieGenre::animation => "animation",
+            MovieGenre::comedy => "comedy",
+            MovieGenre::crime => "crime",
+            MovieGenre::documentary => "documentary",
+            MovieGenre::drama => "drama",
+            MovieGenre::erotica => "erotica",
+            MovieGenre::Family => "family",
+            MovieGenre::fantasy => "fantasy",
+            MovieGenre::foreign => "foreign",
+            MovieGenre::history => "history",
+            MovieGenre::horror => "horror",
+            MovieGenre::music => "music",
+            MovieGenre::mystery => "mystery",
+            MovieGenre::romance => "romance",
+            MovieGenre::science_fiction => "science-fiction",
+            MovieGenre::thriller => "thriller",
+            MovieGenre::tv_movie => "tv-movie",
+            MovieGenre::war => "war",
+            MovieGenre::western => "western",
+        }
+    }
+}
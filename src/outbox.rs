@@ -0,0 +1,526 @@
+//! Queue of sync mutations made offline, sent once a connection is available.
+//!
+//! Redundant operations are merged when queued: adding then removing the same history cancels out, only the last
+//! rating or list move of an item is kept. Consecutive mutations of the same kind are sent in a single payload, in
+//! the order they were queued. A payload refused by the server is split in halves and sent again, so only the
+//! mutations refused on their own are rejected. The result of every sent mutation is kept with the outbox until
+//! taken.
+
+use std::{path::Path, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Result, SimklError},
+    library::{library_key, write_file},
+    rate_limit::{wait_for_slot, RateLimiter},
+    request::{PreparedRequest, SimklRequest},
+    response::SimklResponse,
+    sync::{
+        section, AddToListPayload, HistoryPayload, ListItem, NotFound, RatedItem, RatingsPayload,
+        RemoveHistoryPayload, RemoveRatingsPayload, SyncItem,
+    },
+    MediaIds, MediaType,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Mutation {
+    AddHistory {
+        media_type: MediaType,
+        item: SyncItem,
+    },
+    RemoveHistory {
+        media_type: MediaType,
+        item: SyncItem,
+    },
+    Rate {
+        media_type: MediaType,
+        item: RatedItem,
+    },
+    RemoveRating {
+        media_type: MediaType,
+        ids: MediaIds,
+    },
+    MoveToList {
+        media_type: MediaType,
+        item: ListItem,
+    },
+}
+
+impl Mutation {
+    pub fn media_type(&self) -> MediaType {
+        match self {
+            Mutation::AddHistory { media_type, .. }
+            | Mutation::RemoveHistory { media_type, .. }
+            | Mutation::Rate { media_type, .. }
+            | Mutation::RemoveRating { media_type, .. }
+            | Mutation::MoveToList { media_type, .. } => *media_type,
+        }
+    }
+
+    pub fn ids(&self) -> &MediaIds {
+        match self {
+            Mutation::AddHistory { item, .. } | Mutation::RemoveHistory { item, .. } => &item.ids,
+            Mutation::Rate { item, .. } => &item.ids,
+            Mutation::RemoveRating { ids, .. } => ids,
+            Mutation::MoveToList { item, .. } => &item.ids,
+        }
+    }
+
+    fn same_kind(&self, other: &Mutation) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    fn same_target(&self, other: &Mutation) -> bool {
+        self.media_type() == other.media_type()
+            && library_key(self.ids()) == library_key(other.ids())
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.media_type() == MediaType::Episode {
+            return Err(SimklError::InvalidParameters(
+                "episodes are queued through their show".to_string(),
+            ));
+        }
+        if !self.ids().has_any_id() {
+            return Err(SimklError::InvalidParameters(
+                "queued mutations need an id".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// `mutation` adds the same seasons and episodes to the history, whatever the dates
+fn adds_history(mutation: &Mutation, item: &SyncItem) -> bool {
+    match mutation {
+        Mutation::AddHistory { item: added, .. } => {
+            let (added, item) = (added.clone().without_dates(), item.clone().without_dates());
+            added.seasons == item.seasons && added.episodes == item.episodes
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub id: u64,
+    pub queued_at: DateTime<Utc>,
+    pub mutation: Mutation,
+}
+
+/// Outcome of a sent mutation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "result", content = "reason", rename_all = "snake_case")]
+pub enum EntryResult {
+    Applied,
+    /// The server did not find the media
+    NotFound,
+    /// Rejected by the server, sending it again would fail the same way
+    Rejected(String),
+    /// Accepted by the server, but its response could not be read to tell whether the media was found
+    Unverified,
+}
+
+#[derive(Debug, Default)]
+pub struct FlushReport {
+    pub results: Vec<(OutboxEntry, EntryResult)>,
+    /// Error that stopped the flush, the remaining entries are still queued
+    pub error: Option<SimklError>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Outbox {
+    next_id: u64,
+    entries: Vec<OutboxEntry>,
+    /// Sent entries with their result, see `take_results`
+    #[serde(default)]
+    results: Vec<(OutboxEntry, EntryResult)>,
+}
+
+impl Outbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[OutboxEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Results of the flushes since the last `take_results`, saved with the outbox
+    pub fn results(&self) -> &[(OutboxEntry, EntryResult)] {
+        &self.results
+    }
+
+    pub fn take_results(&mut self) -> Vec<(OutboxEntry, EntryResult)> {
+        std::mem::take(&mut self.results)
+    }
+
+    /// Queue a mutation, merged with the pending ones. Returns the id of the entry, `None` when it cancelled a
+    /// pending one.
+    pub fn push(&mut self, mutation: Mutation) -> Result<Option<u64>> {
+        mutation.validate()?;
+        let target = |e: &OutboxEntry| e.mutation.same_target(&mutation);
+        match &mutation {
+            Mutation::RemoveHistory { item, .. } => {
+                let added = self
+                    .entries
+                    .iter()
+                    .rposition(|e| target(e) && adds_history(&e.mutation, item));
+                if let Some(index) = added {
+                    self.entries.remove(index);
+                    return Ok(None);
+                }
+            }
+            Mutation::AddHistory { item, .. } => self
+                .entries
+                .retain(|e| !(target(e) && adds_history(&e.mutation, item))),
+            // a rating replaces the previous one and a removal makes it useless
+            Mutation::Rate { .. } | Mutation::RemoveRating { .. } => self.entries.retain(|e| {
+                !(target(e)
+                    && matches!(
+                        e.mutation,
+                        Mutation::Rate { .. } | Mutation::RemoveRating { .. }
+                    ))
+            }),
+            Mutation::MoveToList { .. } => self
+                .entries
+                .retain(|e| !(target(e) && matches!(e.mutation, Mutation::MoveToList { .. }))),
+        }
+        self.next_id += 1;
+        self.entries.push(OutboxEntry {
+            id: self.next_id,
+            queued_at: Utc::now(),
+            mutation,
+        });
+        Ok(Some(self.next_id))
+    }
+
+    /// Send the queued mutations in order. The flush stops at the first transport error, rate limit, server
+    /// error or client error other than a refused payload; the outbox should be saved afterwards.
+    pub fn flush<F>(&mut self, limiter: &Mutex<RateLimiter>, send: F) -> FlushReport
+    where
+        F: Fn(&PreparedRequest) -> Result<SimklResponse>,
+    {
+        let mut report = FlushReport::default();
+        // lowered while a refused payload is split to find the refused mutations
+        let mut max_batch = usize::MAX;
+        while !self.entries.is_empty() {
+            let size = self
+                .entries
+                .iter()
+                .take_while(|e| e.mutation.same_kind(&self.entries[0].mutation))
+                .take(max_batch)
+                .count();
+            let response = prepare_batch(&self.entries[..size]).and_then(|request| {
+                wait_for_slot(limiter);
+                send(&request)
+            });
+            let response = match response {
+                Ok(response) => response,
+                Err(e) => {
+                    report.error = Some(e);
+                    break;
+                }
+            };
+            if response.is_success() {
+                let not_found = response.json::<MutationResponse>().map(|r| r.not_found);
+                for entry in self.entries.drain(..size) {
+                    let result = match &not_found {
+                        Ok(not_found) if is_not_found(not_found, &entry.mutation) => {
+                            EntryResult::NotFound
+                        }
+                        Ok(_) => EntryResult::Applied,
+                        Err(_) => EntryResult::Unverified,
+                    };
+                    report.results.push((entry, result));
+                }
+                max_batch = usize::MAX;
+            } else if refuses_payload(response.status_code) {
+                if size > 1 {
+                    max_batch = size / 2;
+                    continue;
+                }
+                let reason = format!("HTTP {}: {}", response.status_code, response.body);
+                let entry = self.entries.remove(0);
+                report.results.push((entry, EntryResult::Rejected(reason)));
+                max_batch = usize::MAX;
+            } else {
+                report.error = Some(SimklError::HttpStatus(response.status_code));
+                break;
+            }
+        }
+        self.results.extend(report.results.iter().cloned());
+        report
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_file(path.as_ref(), &serde_json::to_string(self)?)
+    }
+
+    /// Load a saved outbox, a missing file is an empty outbox
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// The server refused the content of the payload, as opposed to the request itself (authentication, rate
+/// limit...) which would fail the same way for every entry
+fn refuses_payload(status_code: u16) -> bool {
+    matches!(status_code, 400 | 409 | 422)
+}
+
+/// Part shared by the responses of the mutation endpoints
+#[derive(Deserialize)]
+struct MutationResponse {
+    #[serde(default)]
+    not_found: NotFound,
+}
+
+fn is_not_found(not_found: &NotFound, mutation: &Mutation) -> bool {
+    let items = match mutation.media_type() {
+        MediaType::Movie => &not_found.movies,
+        MediaType::Show => &not_found.shows,
+        MediaType::Anime => &not_found.anime,
+        MediaType::Episode => return false,
+    };
+    let key = library_key(mutation.ids());
+    items.iter().any(|i| library_key(&i.ids) == key)
+}
+
+/// Single payload for consecutive mutations of the same kind
+fn prepare_batch(batch: &[OutboxEntry]) -> Result<PreparedRequest> {
+    let mutations = batch.iter().map(|e| &e.mutation);
+    match batch.first().map(|e| &e.mutation) {
+        Some(Mutation::AddHistory { .. }) => {
+            let mut p = HistoryPayload::new();
+            for m in mutations {
+                if let Mutation::AddHistory { media_type, item } = m {
+                    section(*media_type, &mut p.movies, &mut p.shows, &mut p.anime)
+                        .push(item.clone());
+                }
+            }
+            p.prepare()
+        }
+        Some(Mutation::RemoveHistory { .. }) => {
+            let mut p = RemoveHistoryPayload::new();
+            for m in mutations {
+                if let Mutation::RemoveHistory { media_type, item } = m {
+                    section(*media_type, &mut p.movies, &mut p.shows, &mut p.anime)
+                        .push(item.clone().without_dates());
+                }
+            }
+            p.prepare()
+        }
+        Some(Mutation::Rate { .. }) => {
+            let mut p = RatingsPayload::new();
+            for m in mutations {
+                if let Mutation::Rate { media_type, item } = m {
+                    section(*media_type, &mut p.movies, &mut p.shows, &mut p.anime)
+                        .push(item.clone());
+                }
+            }
+            p.prepare()
+        }
+        Some(Mutation::RemoveRating { .. }) => {
+            let mut p = RemoveRatingsPayload::new();
+            for m in mutations {
+                if let Mutation::RemoveRating { media_type, ids } = m {
+                    section(*media_type, &mut p.movies, &mut p.shows, &mut p.anime)
+                        .push(SyncItem::new(ids.clone()));
+                }
+            }
+            p.prepare()
+        }
+        Some(Mutation::MoveToList { .. }) => {
+            let mut p = AddToListPayload::new();
+            for m in mutations {
+                if let Mutation::MoveToList { media_type, item } = m {
+                    section(*media_type, &mut p.movies, &mut p.shows, &mut p.anime)
+                        .push(item.clone());
+                }
+            }
+            p.prepare()
+        }
+        None => Err(SimklError::InvalidParameters("nothing to send".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sync::UserRating, WatchStatus};
+    use std::{cell::RefCell, collections::HashMap, time::Duration};
+
+    fn show(simkl: u32) -> MediaIds {
+        MediaIds::new().with_simkl(simkl)
+    }
+
+    #[test]
+    fn test_outbox() {
+        let mut outbox = Outbox::new();
+        let episode = SyncItem::new(show(1)).with_season(1).with_episodes(1, [3]);
+        outbox
+            .push(Mutation::AddHistory {
+                media_type: MediaType::Show,
+                item: episode.clone(),
+            })
+            .unwrap();
+        let cancelled = outbox.push(Mutation::RemoveHistory {
+            media_type: MediaType::Show,
+            item: episode,
+        });
+        assert_eq!(cancelled.unwrap(), None);
+        assert!(outbox.is_empty());
+
+        for rating in [6, 8] {
+            outbox
+                .push(Mutation::Rate {
+                    media_type: MediaType::Show,
+                    item: RatedItem::new(show(1), UserRating::new(rating).unwrap()),
+                })
+                .unwrap();
+        }
+        outbox
+            .push(Mutation::Rate {
+                media_type: MediaType::Show,
+                item: RatedItem::new(show(2), UserRating::new(5).unwrap()),
+            })
+            .unwrap();
+        outbox
+            .push(Mutation::MoveToList {
+                media_type: MediaType::Show,
+                item: ListItem::new(show(1), WatchStatus::Completed),
+            })
+            .unwrap();
+        assert_eq!(outbox.len(), 3);
+        let outbox: Outbox =
+            serde_json::from_str(&serde_json::to_string(&outbox).unwrap()).unwrap();
+        let mut outbox = outbox;
+
+        let sent = RefCell::new(Vec::new());
+        let limiter = Mutex::new(RateLimiter::new(10, Duration::from_secs(1)));
+        let report = outbox.flush(&limiter, |request| {
+            sent.borrow_mut().push(request.body.clone().unwrap());
+            let body = if request.url.path() == "/sync/ratings" {
+                r#"{"added":{"shows":1},"not_found":{"shows":[{"ids":{"simkl":2}}]}}"#
+            } else {
+                return Ok(SimklResponse::new(503, HashMap::new(), String::new()));
+            };
+            Ok(SimklResponse::new(200, HashMap::new(), body.to_string()))
+        });
+        assert_eq!(
+            sent.borrow()[0],
+            r#"{"shows":[{"ids":{"simkl":1},"rating":8},{"ids":{"simkl":2},"rating":5}]}"#
+        );
+        let results: Vec<EntryResult> = report.results.into_iter().map(|(_, r)| r).collect();
+        assert_eq!(results, vec![EntryResult::Applied, EntryResult::NotFound]);
+        assert!(matches!(report.error, Some(SimklError::HttpStatus(503))));
+        assert_eq!(outbox.len(), 1);
+        let outbox: Outbox =
+            serde_json::from_str(&serde_json::to_string(&outbox).unwrap()).unwrap();
+        assert_eq!(outbox.results().len(), 2);
+    }
+
+    #[test]
+    fn test_refused_batch_is_split() {
+        let mut outbox = Outbox::new();
+        for simkl in 1..=4 {
+            outbox
+                .push(Mutation::MoveToList {
+                    media_type: MediaType::Show,
+                    item: ListItem::new(show(simkl), WatchStatus::Completed),
+                })
+                .unwrap();
+        }
+        let sent = RefCell::new(0);
+        let limiter = Mutex::new(RateLimiter::new(10, Duration::from_secs(1)));
+        let report = outbox.flush(&limiter, |request| {
+            *sent.borrow_mut() += 1;
+            // the server refuses any payload containing show 3
+            let body = request.body.clone().unwrap();
+            if body.contains(r#""simkl":3"#) {
+                return Ok(SimklResponse::new(400, HashMap::new(), String::new()));
+            }
+            Ok(SimklResponse::new(201, HashMap::new(), "{}".to_string()))
+        });
+        let results: Vec<(u32, EntryResult)> = report
+            .results
+            .into_iter()
+            .map(|(e, r)| (e.mutation.ids().simkl.unwrap(), r))
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                (1, EntryResult::Applied),
+                (2, EntryResult::Applied),
+                (3, EntryResult::Rejected("HTTP 400: ".to_string())),
+                (4, EntryResult::Applied),
+            ]
+        );
+        // [1-4], [1-2], [3-4], [3], [4]
+        assert_eq!(*sent.borrow(), 5);
+        assert!(outbox.is_empty());
+        assert_eq!(outbox.take_results().len(), 4);
+        assert!(outbox.results().is_empty());
+    }
+
+    #[test]
+    fn test_unauthorized_flush_keeps_entries() {
+        let mut outbox = Outbox::new();
+        for simkl in 1..=2 {
+            outbox
+                .push(Mutation::MoveToList {
+                    media_type: MediaType::Show,
+                    item: ListItem::new(show(simkl), WatchStatus::Completed),
+                })
+                .unwrap();
+        }
+        let sent = RefCell::new(0);
+        let limiter = Mutex::new(RateLimiter::new(10, Duration::from_secs(1)));
+        let report = outbox.flush(&limiter, |_| {
+            *sent.borrow_mut() += 1;
+            Ok(SimklResponse::new(401, HashMap::new(), String::new()))
+        });
+        assert_eq!(*sent.borrow(), 1);
+        assert!(report.results.is_empty());
+        assert!(matches!(report.error, Some(SimklError::HttpStatus(401))));
+        assert_eq!(outbox.len(), 2);
+        assert!(outbox.results().is_empty());
+    }
+
+    #[test]
+    fn test_unreadable_response_is_unverified() {
+        let mut outbox = Outbox::new();
+        outbox
+            .push(Mutation::RemoveRating {
+                media_type: MediaType::Movie,
+                ids: show(1),
+            })
+            .unwrap();
+        let limiter = Mutex::new(RateLimiter::new(10, Duration::from_secs(1)));
+        let report = outbox.flush(&limiter, |_| {
+            Ok(SimklResponse::new(
+                200,
+                HashMap::new(),
+                "<html>".to_string(),
+            ))
+        });
+        assert_eq!(report.results[0].1, EntryResult::Unverified);
+        assert!(outbox.is_empty());
+    }
+}
@@ -7,7 +7,7 @@ use crate::API_URL;
 /// Request a device code URL
 pub fn get_pin_request(client_id: String, redirect_url: String) -> String {
     let mut result = String::from(API_URL);
-    result.push_str("oauth/pin?client_id=");
+    result.push_str("/oauth/pin?client_id=");
     result.push_str(&client_id);
     result.push_str("&redirect=");
     result.push_str(&redirect_url);
@@ -43,7 +43,7 @@ pub struct CodeResponse {
 
 pub fn get_code_status_request(user_code: String, client_id: String) -> String {
     let mut result = String::from(API_URL);
-    result.push_str("oauth/pin/");
+    result.push_str("/oauth/pin/");
     result.push_str(&user_code);
     result.push_str("?client_id=");
     result.push_str(&client_id);
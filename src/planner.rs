@@ -0,0 +1,516 @@
+//! Incremental synchronization, following the algorithm recommended by SIMKL:
+//!
+//! 1. get `/sync/activities` and compare it with the snapshot of the previous sync,
+//! 2. only download the lists that changed, with `date_from` set to the previous timestamp,
+//! 3. when ratings or removals changed, get the whole section again since deltas do not report them.
+
+use std::{fs, path::Path};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Result, SimklError},
+    library::{write_file, Library},
+    request::{PreparedRequest, SimklRequest},
+    response::SimklResponse,
+    sync::{Activities, AllItems, AllItemsRequest, MediaActivity},
+    MediaType, WatchStatus,
+};
+
+/// Sections of the library in the order they are synchronized
+pub const LIBRARY_TYPES: [MediaType; 3] = [MediaType::Show, MediaType::Anime, MediaType::Movie];
+
+/// Lists with their own timestamp in `MediaActivity`
+const TRACKED_STATUSES: [WatchStatus; 5] = [
+    WatchStatus::Watching,
+    WatchStatus::PlanToWatch,
+    WatchStatus::Hold,
+    WatchStatus::Completed,
+    WatchStatus::Dropped,
+];
+
+/// What to download to bring a local copy of the library up to date
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct SyncPlan {
+    /// `all-items` calls to send, in order
+    pub calls: Vec<AllItemsRequest>,
+    /// Sections whose ratings changed and must be downloaded again
+    pub ratings_resync: Vec<MediaType>,
+    /// Sections with removed items, the full id list is needed to find them (see `removals_request`)
+    pub removals_resync: Vec<MediaType>,
+}
+
+impl SyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty() && self.ratings_resync.is_empty() && self.removals_resync.is_empty()
+    }
+}
+
+/// Request listing only the ids of a section, to find the items removed from the library
+pub fn removals_request(media_type: MediaType) -> AllItemsRequest {
+    AllItemsRequest::new()
+        .with_type(media_type)
+        .with_extended("simkl_ids_only")
+}
+
+fn changed(new: Option<DateTime<Utc>>, old: Option<DateTime<Utc>>) -> bool {
+    match (new, old) {
+        (Some(new), Some(old)) => new > old,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+fn plan_media(
+    plan: &mut SyncPlan,
+    media_type: MediaType,
+    new: &MediaActivity,
+    old: &MediaActivity,
+) {
+    if new.all <= old.all {
+        return;
+    }
+    if new.removed_from_list > old.removed_from_list {
+        plan.removals_resync.push(media_type);
+    }
+    if new.rated_at > old.rated_at {
+        plan.ratings_resync.push(media_type);
+    }
+
+    let statuses: Vec<WatchStatus> = TRACKED_STATUSES
+        .into_iter()
+        .filter(|s| s.applies_to(media_type))
+        .filter(|s| changed(new.status(*s), old.status(*s)))
+        .collect();
+    let tracked = TRACKED_STATUSES
+        .iter()
+        .filter(|s| s.applies_to(media_type))
+        .count();
+    let explained = !statuses.is_empty()
+        || new.rated_at > old.rated_at
+        || new.removed_from_list > old.removed_from_list;
+
+    let request = AllItemsRequest::new()
+        .with_type(media_type)
+        .with_date_from(old.all);
+    if statuses.len() == tracked || !explained {
+        // Everything changed, or a change no list timestamp accounts for (e.g. `notinteresting`)
+        plan.calls.push(request);
+    } else {
+        plan.calls
+            .extend(statuses.into_iter().map(|s| request.clone().with_status(s)));
+    }
+}
+
+/// Compare fresh activities with the snapshot stored after the last sync. Without snapshot the whole library is
+/// downloaded.
+pub fn plan_sync(current: &Activities, previous: Option<&Activities>) -> SyncPlan {
+    let mut plan = SyncPlan::default();
+    let previous = match previous {
+        Some(previous) => previous,
+        None => {
+            plan.calls = LIBRARY_TYPES
+                .into_iter()
+                .map(|t| AllItemsRequest::new().with_type(t))
+                .collect();
+            return plan;
+        }
+    };
+    if current.all <= previous.all {
+        return plan;
+    }
+    for media_type in LIBRARY_TYPES {
+        if let (Some(new), Some(old)) = (current.media(media_type), previous.media(media_type)) {
+            plan_media(&mut plan, media_type, new, old);
+        }
+    }
+    plan
+}
+
+/// One `all-items` call of a sync run, stored so an interrupted run can be resumed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncStep {
+    pub media_type: MediaType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<WatchStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_from: Option<DateTime<Utc>>,
+    /// Only list the ids to find removed items
+    #[serde(default)]
+    pub ids_only: bool,
+}
+
+impl SyncStep {
+    /// Download of a whole section
+    pub fn is_full(&self) -> bool {
+        self.status.is_none() && self.date_from.is_none() && !self.ids_only
+    }
+
+    pub fn request(&self) -> AllItemsRequest {
+        if self.ids_only {
+            return removals_request(self.media_type);
+        }
+        let mut request = AllItemsRequest::new().with_type(self.media_type);
+        request.status = self.status;
+        request.date_from = self.date_from;
+        request
+    }
+}
+
+/// Fails for requests without media type, which target every section at once
+impl TryFrom<&AllItemsRequest> for SyncStep {
+    type Error = SimklError;
+
+    fn try_from(request: &AllItemsRequest) -> Result<Self> {
+        let media_type = request.media_type.ok_or_else(|| {
+            SimklError::InvalidParameters("a sync step needs a media type".to_string())
+        })?;
+        Ok(Self {
+            media_type,
+            status: request.status,
+            date_from: request.date_from,
+            ids_only: request.extended.as_deref() == Some("simkl_ids_only"),
+        })
+    }
+}
+
+impl SyncPlan {
+    /// Calls to send in order. Ratings are refreshed by downloading the whole section again, removals are found
+    /// with the id lists. Fails when a call has no media type.
+    pub fn steps(&self) -> Result<Vec<SyncStep>> {
+        let step = |media_type: &MediaType, ids_only| SyncStep {
+            media_type: *media_type,
+            status: None,
+            date_from: None,
+            ids_only,
+        };
+        let full: Vec<SyncStep> = self.ratings_resync.iter().map(|t| step(t, false)).collect();
+        let calls = self
+            .calls
+            .iter()
+            .map(SyncStep::try_from)
+            .collect::<Result<Vec<SyncStep>>>()?;
+        let steps: Vec<SyncStep> = calls
+            .into_iter()
+            // a full download of the section already contains the changes
+            .filter(|s| !full.iter().any(|f| f.media_type == s.media_type))
+            .chain(full.iter().cloned())
+            .collect();
+        // and replaces the section, dropping the removed items
+        let removals: Vec<SyncStep> = self
+            .removals_resync
+            .iter()
+            .filter(|t| !steps.iter().any(|s| s.media_type == **t && s.is_full()))
+            .map(|t| step(t, true))
+            .collect();
+        Ok(steps.into_iter().chain(removals).collect())
+    }
+}
+
+/// Sync being run: the activities to record once done and the steps left
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncRun {
+    pub target: Activities,
+    pub steps: Vec<SyncStep>,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Sync run in progress, saved after every step once the library is saved (see `run_sync`). The cursor of the last
+/// completed sync is `Library::activities`, stored with the library.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncState {
+    #[serde(default)]
+    pub in_progress: Option<SyncRun>,
+}
+
+impl SyncState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Plan a sync of `library` toward `current`, or resume the interrupted one. A resumed run keeps its target:
+    /// changes made since are picked by the next sync.
+    pub fn begin(
+        &mut self,
+        library: &mut Library,
+        current: Activities,
+    ) -> Result<Option<&SyncStep>> {
+        if self.in_progress.is_none() {
+            let steps = library.plan(&current).steps()?;
+            if steps.is_empty() {
+                library.record_activities(current);
+            } else {
+                self.in_progress = Some(SyncRun {
+                    target: current,
+                    steps,
+                    started_at: Utc::now(),
+                });
+            }
+        }
+        Ok(self.next_step())
+    }
+
+    pub fn next_step(&self) -> Option<&SyncStep> {
+        self.in_progress.as_ref().and_then(|run| run.steps.first())
+    }
+
+    /// Mark the current step as applied to `library`, the run is recorded in it after the last one
+    pub fn complete_step(&mut self, library: &mut Library) {
+        let Some(run) = self.in_progress.as_mut() else {
+            return;
+        };
+        if !run.steps.is_empty() {
+            run.steps.remove(0);
+        }
+        if run.steps.is_empty() {
+            if let Some(run) = self.in_progress.take() {
+                library.record_activities(run.target);
+            }
+        }
+    }
+
+    pub fn is_in_progress(&self) -> bool {
+        self.in_progress.is_some()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_file(path.as_ref(), &serde_json::to_string(self)?)
+    }
+
+    /// Load a saved state, a missing file is a first sync
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Bring `library` up to date with `current` (response of `/sync/activities`). After every applied step the library
+/// is persisted with `save_library`, then the state with `save_state`; on error the run stops and can be resumed
+/// later with the saved state.
+///
+/// The library is always saved first: after a crash between the two saves the resumed run applies the last step
+/// again, which gives the same library, instead of skipping a step the library never received.
+pub fn run_sync<F, L, S>(
+    state: &mut SyncState,
+    library: &mut Library,
+    current: Activities,
+    send: F,
+    mut save_library: L,
+    mut save_state: S,
+) -> Result<()>
+where
+    F: Fn(&PreparedRequest) -> Result<SimklResponse>,
+    L: FnMut(&Library) -> Result<()>,
+    S: FnMut(&SyncState) -> Result<()>,
+{
+    let mut step = state.begin(library, current)?.cloned();
+    if step.is_none() {
+        // nothing to download, the new activities may still have been recorded
+        return save_library(library);
+    }
+    while let Some(current_step) = step {
+        let request = current_step.request();
+        let response = send(&request.prepare()?)?.error_for_status()?;
+        library.apply(&request, AllItems::from_body(&response.body)?);
+        state.complete_step(library);
+        save_library(library)?;
+        save_state(state)?;
+        step = state.next_step().cloned();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MediaIds;
+    use chrono::{Duration, TimeZone};
+    use std::{cell::RefCell, collections::HashMap};
+
+    fn snapshot(date: DateTime<Utc>) -> Activities {
+        let media = MediaActivity {
+            all: date,
+            rated_at: date,
+            plan_to_watch: date,
+            watching: Some(date),
+            completed: date,
+            hold: Some(date),
+            dropped: date,
+            removed_from_list: date,
+        };
+        Activities {
+            all: date,
+            settings: Default::default(),
+            tv_shows: media.clone(),
+            anime: media.clone(),
+            movies: MediaActivity {
+                watching: None,
+                hold: None,
+                ..media
+            },
+        }
+    }
+
+    #[test]
+    fn test_sync_state() {
+        let before = Utc.with_ymd_and_hms(2024, 3, 1, 20, 0, 0).unwrap();
+        let mut previous = snapshot(before);
+        let mut library = Library::new();
+        library.record_activities(previous.clone());
+        let mut state = SyncState::new();
+        previous.all = before + Duration::hours(1);
+        previous.movies.all = previous.all;
+        previous.movies.completed = previous.all;
+        previous.movies.rated_at = previous.all;
+        previous.anime.all = previous.all;
+        previous.anime.removed_from_list = previous.all;
+
+        let step = state
+            .begin(&mut library, previous.clone())
+            .unwrap()
+            .cloned()
+            .unwrap();
+        // the completed movies are part of the full download made for ratings
+        assert_eq!(
+            step.request(),
+            AllItemsRequest::new().with_type(MediaType::Movie)
+        );
+        state.complete_step(&mut library);
+        assert_ne!(library.activities.as_ref(), Some(&previous));
+
+        // interrupted: resumed from the saved state
+        let mut state: SyncState =
+            serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+        let step = state
+            .begin(&mut library, snapshot(before + Duration::hours(2)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(step.request(), removals_request(MediaType::Anime));
+        state.complete_step(&mut library);
+        assert!(!state.is_in_progress());
+        assert_eq!(library.activities, Some(previous));
+    }
+
+    #[test]
+    fn test_plan_sync() {
+        let before = Utc.with_ymd_and_hms(2024, 3, 1, 20, 0, 0).unwrap();
+        let after = before + Duration::hours(1);
+        let previous = snapshot(before);
+
+        assert_eq!(plan_sync(&previous, None).calls.len(), 3);
+        assert!(plan_sync(&previous, Some(&previous)).is_empty());
+        let plan = SyncPlan {
+            calls: vec![AllItemsRequest::new()],
+            ..Default::default()
+        };
+        assert!(plan.steps().is_err());
+
+        let mut current = previous.clone();
+        current.all = after;
+        current.tv_shows.all = after;
+        current.tv_shows.watching = Some(after);
+        current.tv_shows.completed = after;
+        current.movies.all = after;
+        current.movies.rated_at = after;
+        current.movies.removed_from_list = after;
+        let plan = plan_sync(&current, Some(&previous));
+        assert_eq!(
+            plan.calls,
+            vec![
+                AllItemsRequest::new()
+                    .with_type(MediaType::Show)
+                    .with_status(WatchStatus::Watching)
+                    .with_date_from(before),
+                AllItemsRequest::new()
+                    .with_type(MediaType::Show)
+                    .with_status(WatchStatus::Completed)
+                    .with_date_from(before),
+            ]
+        );
+        assert_eq!(plan.ratings_resync, vec![MediaType::Movie]);
+        assert_eq!(plan.removals_resync, vec![MediaType::Movie]);
+        // the full download of the movies replaces the section, removals included
+        let steps = plan.steps().unwrap();
+        assert_eq!(steps.len(), 3);
+        assert!(steps.iter().all(|s| !s.ids_only));
+
+        let mut current = previous.clone();
+        current.all = after;
+        current.anime.all = after;
+        let plan = plan_sync(&current, Some(&previous));
+        assert_eq!(
+            plan.calls,
+            vec![AllItemsRequest::new()
+                .with_type(MediaType::Anime)
+                .with_date_from(before)]
+        );
+    }
+
+    #[test]
+    fn test_crash_between_saves() {
+        let before = Utc.with_ymd_and_hms(2024, 3, 1, 20, 0, 0).unwrap();
+        let mut library = Library::new();
+        library.record_activities(snapshot(before));
+        let mut current = snapshot(before);
+        current.all = before + Duration::hours(1);
+        current.tv_shows.all = current.all;
+        current.tv_shows.watching = Some(current.all);
+        current.movies.all = current.all;
+        current.movies.completed = current.all;
+
+        let send = |request: &PreparedRequest| {
+            let body = if request.url.path().contains("movies") {
+                r#"{"movies":[{"status":"completed","movie":{"title":"Heat","ids":{"simkl":2}}}]}"#
+            } else {
+                r#"{"shows":[{"status":"watching","show":{"title":"Lost","ids":{"simkl":1}}}]}"#
+            };
+            Ok(SimklResponse::new(200, HashMap::new(), body.to_string()))
+        };
+        let saved_library = RefCell::new(String::new());
+        let saved_state = RefCell::new(String::new());
+        let save_library = |library: &Library| {
+            *saved_library.borrow_mut() = library.to_json()?;
+            Ok(())
+        };
+
+        // the process dies after saving the library of the last step, before its state
+        let mut state = SyncState::new();
+        let result = run_sync(
+            &mut state,
+            &mut library,
+            current.clone(),
+            send,
+            save_library,
+            |state: &SyncState| {
+                if !state.is_in_progress() {
+                    return Err(SimklError::Transport("crash".to_string()));
+                }
+                *saved_state.borrow_mut() = serde_json::to_string(state)?;
+                Ok(())
+            },
+        );
+        assert!(result.is_err());
+
+        let mut library = Library::from_json(&saved_library.borrow()).unwrap();
+        let mut state: SyncState = serde_json::from_str(&saved_state.borrow()).unwrap();
+        assert_eq!(library.activities.as_ref(), Some(&current));
+        assert!(state.is_in_progress());
+        run_sync(
+            &mut state,
+            &mut library,
+            snapshot(before + Duration::hours(2)),
+            send,
+            save_library,
+            |_: &SyncState| Ok(()),
+        )
+        .unwrap();
+        assert!(!state.is_in_progress());
+        assert_eq!(library.activities, Some(current));
+        assert!(library.find(&MediaIds::new().with_simkl(1)).is_some());
+        assert!(library.find(&MediaIds::new().with_simkl(2)).is_some());
+    }
+}
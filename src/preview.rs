@@ -0,0 +1,311 @@
+//! Preview of a sync payload: what each item would change in the library, without sending anything.
+//!
+//! Items are matched by any of their ids (see `Library::get`), items without id by title and year.
+
+use crate::{
+    library::Library,
+    sync::{
+        sections, AddToListPayload, AllItems, HistoryPayload, LibraryItem, RatingsPayload,
+        SyncItem, UserRating,
+    },
+    MediaIds, MediaType, WatchStatus,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Not in the library yet
+    New,
+    /// Moved to another list, `from` is `None` when the item is in no list
+    Status {
+        from: Option<WatchStatus>,
+        to: WatchStatus,
+    },
+    /// Rated again, `from` is `None` when the item was not rated
+    Rating {
+        from: Option<UserRating>,
+        to: UserRating,
+    },
+    /// Number of episodes added to the history
+    Episodes(usize),
+    /// Already in this state
+    NoOp,
+    /// Cannot be matched without asking the API
+    Unresolvable(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemDiff {
+    pub media_type: MediaType,
+    pub ids: MediaIds,
+    pub title: Option<String>,
+    pub change: Change,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct DiffSummary {
+    pub new: usize,
+    pub changed: usize,
+    pub no_op: usize,
+    pub unresolvable: usize,
+}
+
+/// Changes of every item, in the order of the payload
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct DiffReport {
+    pub items: Vec<ItemDiff>,
+}
+
+impl DiffReport {
+    pub fn summary(&self) -> DiffSummary {
+        let mut summary = DiffSummary::default();
+        for item in &self.items {
+            match item.change {
+                Change::New => summary.new += 1,
+                Change::Status { .. } | Change::Rating { .. } | Change::Episodes(_) => {
+                    summary.changed += 1
+                }
+                Change::NoOp => summary.no_op += 1,
+                Change::Unresolvable(_) => summary.unresolvable += 1,
+            }
+        }
+        summary
+    }
+
+    fn push(
+        &mut self,
+        media_type: MediaType,
+        ids: &MediaIds,
+        title: Option<&String>,
+        change: Change,
+    ) {
+        self.items.push(ItemDiff {
+            media_type,
+            ids: ids.clone(),
+            title: title.cloned(),
+            change,
+        });
+    }
+}
+
+/// Payload that can be compared with the library
+pub trait Diff {
+    fn diff(&self, snapshot: &AllItems) -> DiffReport {
+        self.diff_library(&Library::from(snapshot.clone()))
+    }
+
+    fn diff_library(&self, library: &Library) -> DiffReport;
+}
+
+/// Item of the library targeted by a payload item, `Err` when it cannot be told
+fn resolve<'a>(
+    library: &'a Library,
+    media_type: MediaType,
+    ids: &MediaIds,
+    title: Option<&String>,
+    year: Option<u16>,
+) -> Result<Option<&'a LibraryItem>, String> {
+    // an unknown id is a media the library does not have yet, even if another one has the same title
+    if ids.has_any_id() {
+        return Ok(library.get(media_type, ids));
+    }
+    let title = title.ok_or_else(|| "no id nor title".to_string())?;
+    library
+        .section(media_type)
+        .and_then(|section| {
+            section.values().find(|item| {
+                item.media().is_some_and(|m| {
+                    m.title.eq_ignore_ascii_case(title) && (year.is_none() || m.year == year)
+                })
+            })
+        })
+        .map(Some)
+        .ok_or_else(|| format!("no id and \"{}\" is not in the library", title))
+}
+
+/// Episodes of a history item that were not watched, `None` when whole titles or seasons are targeted
+fn new_episodes(item: &SyncItem, previous: &LibraryItem) -> Option<usize> {
+    if item.is_whole() || item.seasons.iter().any(|s| s.episodes.is_empty()) {
+        return None;
+    }
+    let seasons = item
+        .seasons
+        .iter()
+        .flat_map(|s| s.episodes.iter().map(move |e| (s.number, e.number)))
+        .filter(|(s, e)| !previous.is_episode_watched(*s, *e))
+        .count();
+    let absolute = item
+        .episodes
+        .iter()
+        .filter(|e| {
+            !previous
+                .seasons
+                .iter()
+                .any(|s| s.episodes.iter().any(|w| w.number == e.number))
+        })
+        .count();
+    Some(seasons + absolute)
+}
+
+impl Diff for HistoryPayload {
+    fn diff_library(&self, library: &Library) -> DiffReport {
+        let mut report = DiffReport::default();
+        for (media_type, item) in sections(&self.movies, &self.shows, &self.anime) {
+            let title = item.title.as_ref();
+            let change = match resolve(library, media_type, &item.ids, title, item.year) {
+                Err(reason) => Change::Unresolvable(reason),
+                Ok(None) => Change::New,
+                Ok(Some(previous)) => match new_episodes(item, previous) {
+                    Some(0) => Change::NoOp,
+                    Some(count) => Change::Episodes(count),
+                    None if previous.status == Some(WatchStatus::Completed) => Change::NoOp,
+                    None => Change::Status {
+                        from: previous.status,
+                        to: WatchStatus::Completed,
+                    },
+                },
+            };
+            report.push(media_type, &item.ids, title, change);
+        }
+        for episode in &self.episodes {
+            let change = Change::Unresolvable("episode identified by its own ids".to_string());
+            report.push(MediaType::Episode, &episode.ids, None, change);
+        }
+        report
+    }
+}
+
+impl Diff for AddToListPayload {
+    fn diff_library(&self, library: &Library) -> DiffReport {
+        let mut report = DiffReport::default();
+        for (media_type, item) in sections(&self.movies, &self.shows, &self.anime) {
+            let title = item.title.as_ref();
+            let change = match resolve(library, media_type, &item.ids, title, item.year) {
+                Err(reason) => Change::Unresolvable(reason),
+                Ok(None) => Change::New,
+                Ok(Some(previous)) if previous.status == Some(item.to) => Change::NoOp,
+                Ok(Some(previous)) => Change::Status {
+                    from: previous.status,
+                    to: item.to,
+                },
+            };
+            report.push(media_type, &item.ids, title, change);
+        }
+        report
+    }
+}
+
+impl Diff for RatingsPayload {
+    fn diff_library(&self, library: &Library) -> DiffReport {
+        let mut report = DiffReport::default();
+        for (media_type, item) in sections(&self.movies, &self.shows, &self.anime) {
+            let title = item.title.as_ref();
+            let change = match resolve(library, media_type, &item.ids, title, item.year) {
+                Err(reason) => Change::Unresolvable(reason),
+                Ok(None) => Change::New,
+                Ok(Some(previous)) if previous.user_rating == Some(item.rating) => Change::NoOp,
+                Ok(Some(previous)) => Change::Rating {
+                    from: previous.user_rating,
+                    to: item.rating,
+                },
+            };
+            report.push(media_type, &item.ids, title, change);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::{
+        LibraryMedia, ListItem, RatedItem, UserRating, WatchedEpisode, WatchedSeason,
+    };
+
+    fn snapshot() -> AllItems {
+        AllItems {
+            shows: vec![LibraryItem {
+                status: Some(WatchStatus::Watching),
+                user_rating: Some(UserRating::new(8).unwrap()),
+                show: Some(LibraryMedia {
+                    title: "Breaking Bad".to_string(),
+                    year: Some(2008),
+                    ids: MediaIds::new()
+                        .with_simkl(1)
+                        .with_imdb("tt0903747".to_string()),
+                    ..Default::default()
+                }),
+                seasons: vec![WatchedSeason {
+                    number: 1,
+                    episodes: vec![WatchedEpisode {
+                        number: 1,
+                        watched_at: None,
+                    }],
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_history_diff() {
+        let mut by_title = SyncItem::new(MediaIds::default()).with_episodes(1, [1]);
+        by_title.title = Some("breaking bad".to_string());
+        let payload = HistoryPayload::new()
+            .add_show(SyncItem::new(MediaIds::new().with_simkl(1)).with_episodes(1, [1, 2, 3]))
+            .add_show(by_title)
+            .add_show(SyncItem::new(MediaIds::new().with_simkl(2)))
+            .add_movie(SyncItem::new(MediaIds::default()).with_title("Unknown".to_string()));
+        let report = payload.diff(&snapshot());
+        let changes: Vec<Change> = report.items.iter().map(|i| i.change.clone()).collect();
+        assert_eq!(
+            changes,
+            vec![
+                Change::Unresolvable("no id and \"Unknown\" is not in the library".to_string()),
+                Change::Episodes(2),
+                Change::NoOp,
+                Change::New,
+            ]
+        );
+        assert_eq!(report.summary().changed, 1);
+    }
+
+    #[test]
+    fn test_list_and_ratings_diff() {
+        let ids = MediaIds::new().with_simkl(1);
+        let list =
+            AddToListPayload::new().add_show(ListItem::new(ids.clone(), WatchStatus::Completed));
+        assert_eq!(
+            list.diff(&snapshot()).items[0].change,
+            Change::Status {
+                from: Some(WatchStatus::Watching),
+                to: WatchStatus::Completed
+            }
+        );
+        let ratings = RatingsPayload::new()
+            .add_show(RatedItem::new(ids.clone(), UserRating::new(8).unwrap()))
+            .add_show(RatedItem::new(ids, UserRating::new(9).unwrap()));
+        let report = ratings.diff(&snapshot());
+        assert_eq!(report.items[0].change, Change::NoOp);
+        assert_eq!(
+            report.items[1].change,
+            Change::Rating {
+                from: UserRating::new(8).ok(),
+                to: UserRating::new(9).unwrap()
+            }
+        );
+    }
+
+    #[test]
+    fn test_diff_by_other_id() {
+        let imdb = MediaIds::new().with_imdb("tt0903747".to_string());
+        let payload = HistoryPayload::new().add_show(SyncItem::new(imdb).with_episodes(1, [1]));
+        assert_eq!(payload.diff(&snapshot()).items[0].change, Change::NoOp);
+
+        // another media with the same title
+        let mut other = SyncItem::new(MediaIds::new().with_simkl(2));
+        other.title = Some("Breaking Bad".to_string());
+        let payload = HistoryPayload::new().add_show(other);
+        assert_eq!(payload.diff(&snapshot()).items[0].change, Change::New);
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::VecDeque;
+use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone)]
@@ -60,3 +61,20 @@ impl RateLimiter {
         }
     }
 }
+
+/// Block until the shared limiter lets one more request go
+pub(crate) fn wait_for_slot(limiter: &Mutex<RateLimiter>) {
+    loop {
+        let wait = {
+            let mut limiter = limiter.lock().unwrap_or_else(|e| e.into_inner());
+            if limiter.can_make_request() {
+                limiter.record_request();
+                return;
+            }
+            limiter
+                .time_until_next_request()
+                .unwrap_or(Duration::from_millis(10))
+        };
+        std::thread::sleep(wait);
+    }
+}
@@ -0,0 +1,259 @@
+//! Aggregated ratings of a title without fetching its full metadata (token not required)
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{
+    error::{Result, SimklError},
+    library::library_key,
+    rate_limit::{wait_for_slot, RateLimiter},
+    request::{PreparedRequest, SimklRequest},
+    response::SimklResponse,
+    MediaIds, Ratings,
+};
+
+/// Data to include in the ratings response
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RatingField {
+    Simkl,
+    /// External ratings (IMDb, MAL)
+    Ext,
+    Imdb,
+    Mal,
+    Rank,
+    Droprate,
+    Reactions,
+    HasTrailer,
+}
+
+impl RatingField {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RatingField::Simkl => "simkl",
+            RatingField::Ext => "ext",
+            RatingField::Imdb => "imdb",
+            RatingField::Mal => "mal",
+            RatingField::Rank => "rank",
+            RatingField::Droprate => "droprate",
+            RatingField::Reactions => "reactions",
+            RatingField::HasTrailer => "has_trailer",
+        }
+    }
+}
+
+/// Get the ratings of an item by SIMKL, IMDb or MAL id, the response is a `Ratings`.
+///
+/// Example: `GET https://api.simkl.com/ratings?simkl=53536&fields=simkl,imdb,rank`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatingsRequest {
+    pub ids: MediaIds,
+    /// All the fields are returned when empty
+    pub fields: Vec<RatingField>,
+}
+
+impl RatingsRequest {
+    pub fn new(ids: MediaIds) -> Self {
+        Self {
+            ids,
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn with_field(mut self, field: RatingField) -> Self {
+        if !self.fields.contains(&field) {
+            self.fields.push(field);
+        }
+        self
+    }
+
+    pub fn with_fields(self, fields: impl IntoIterator<Item = RatingField>) -> Self {
+        fields.into_iter().fold(self, |r, f| r.with_field(f))
+    }
+}
+
+impl SimklRequest for RatingsRequest {
+    fn endpoint(&self) -> String {
+        "/ratings".to_string()
+    }
+
+    fn query_params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        if let Some(id) = self.ids.simkl {
+            params.push(("simkl".to_string(), id.to_string()));
+        }
+        if let Some(ref id) = self.ids.imdb {
+            params.push(("imdb".to_string(), id.clone()));
+        }
+        if let Some(id) = self.ids.mal {
+            params.push(("mal".to_string(), id.to_string()));
+        }
+        if !self.fields.is_empty() {
+            let fields: Vec<&str> = self.fields.iter().map(|f| f.as_str()).collect();
+            params.push(("fields".to_string(), fields.join(",")));
+        }
+        params
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.ids.simkl.is_none() && self.ids.imdb.is_none() && self.ids.mal.is_none() {
+            return Err(SimklError::InvalidParameters(
+                "a simkl, imdb or mal id is required".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Result of `batch_ratings`, items that failed do not prevent the others from being fetched
+#[derive(Debug, Default)]
+pub struct BatchRatings {
+    pub ratings: HashMap<MediaIds, Ratings>,
+    pub failures: Vec<(MediaIds, SimklError)>,
+}
+
+fn fetch_ratings<F>(ids: &MediaIds, fields: &[RatingField], send: &F) -> Result<Ratings>
+where
+    F: Fn(&PreparedRequest) -> Result<SimklResponse>,
+{
+    let request = RatingsRequest::new(ids.clone())
+        .with_fields(fields.iter().copied())
+        .prepare()?;
+    send(&request)?.error_for_status()?.json()
+}
+
+/// Get the ratings of many titles at once. Duplicated ids are fetched once, at most `concurrency` requests are in
+/// flight and every request waits for the shared `limiter`.
+///
+/// `send` performs the HTTP call with the client of your choice, it is called from several threads. A panic in `send`
+/// is propagated once every worker stopped.
+pub fn batch_ratings<F>(
+    ids: impl IntoIterator<Item = MediaIds>,
+    fields: &[RatingField],
+    concurrency: usize,
+    limiter: &Mutex<RateLimiter>,
+    send: F,
+) -> BatchRatings
+where
+    F: Fn(&PreparedRequest) -> Result<SimklResponse> + Sync,
+{
+    let mut seen = HashSet::new();
+    // the same title given with more ids is fetched once
+    let ids: Vec<MediaIds> = ids
+        .into_iter()
+        .filter(|i| seen.insert(library_key(i)))
+        .collect();
+    let next = AtomicUsize::new(0);
+    let workers = concurrency.clamp(1, ids.len().max(1));
+
+    let results: Vec<(MediaIds, Result<Ratings>)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = ids.get(index) else {
+                            break;
+                        };
+                        wait_for_slot(limiter);
+                        results.push((item.clone(), fetch_ratings(item, fields, &send)));
+                    }
+                    results
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    });
+
+    let mut batch = BatchRatings::default();
+    for (ids, result) in results {
+        match result {
+            Ok(ratings) => {
+                batch.ratings.insert(ids, ratings);
+            }
+            Err(err) => batch.failures.push((ids, err)),
+        }
+    }
+    batch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ratings;
+    use std::time::Duration;
+
+    #[test]
+    fn test_ratings_request() {
+        let request = RatingsRequest::new(MediaIds::new().with_simkl(53536)).with_fields([
+            RatingField::Simkl,
+            RatingField::Imdb,
+            RatingField::Rank,
+        ]);
+        assert!(request.validate().is_ok());
+        assert_eq!(
+            request.build_url(),
+            "https://api.simkl.com/ratings?simkl=53536&fields=simkl%2Cimdb%2Crank"
+        );
+        assert!(RatingsRequest::new(MediaIds::new().with_tmdb(245891))
+            .validate()
+            .is_err());
+
+        let json = r#"{"id":53536,"link":"https://simkl.com/movies/53536/john-wick","simkl":{"rating":7.8,"votes":4412},
+            "imdb":{"rating":7.4,"votes":700000},"droprate":"2.5%","reactions":{"like":12},"has_trailer":true}"#;
+        let ratings: Ratings = serde_json::from_str(json).unwrap();
+        assert_eq!(ratings.simkl.unwrap().votes, Some(4412));
+        assert_eq!(ratings.droprate, Some(2.5));
+        assert_eq!(ratings.reactions.get("like"), Some(&12));
+    }
+
+    #[test]
+    fn test_batch_ratings() {
+        let limiter = Mutex::new(RateLimiter::new(100, Duration::from_secs(1)));
+        let calls = AtomicUsize::new(0);
+        let ids = vec![
+            MediaIds::new().with_simkl(1),
+            MediaIds::new().with_simkl(2),
+            MediaIds::new()
+                .with_simkl(1)
+                .with_imdb("tt0903747".to_string()),
+            MediaIds::new().with_simkl(404),
+        ];
+        let batch = batch_ratings(ids, &[RatingField::Simkl], 2, &limiter, |request| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            let id = request
+                .url
+                .query_pairs()
+                .find(|(k, _)| k == "simkl")
+                .unwrap()
+                .1
+                .to_string();
+            let status = if id == "404" { 404 } else { 200 };
+            let body = format!(r#"{{"id":{},"simkl":{{"rating":8.0,"votes":10}}}}"#, id);
+            Ok(SimklResponse::new(status, HashMap::new(), body))
+        });
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+        assert_eq!(batch.ratings.len(), 2);
+        assert_eq!(batch.ratings[&MediaIds::new().with_simkl(2)].id, 2);
+        assert_eq!(batch.failures.len(), 1);
+        assert!(matches!(batch.failures[0].1, SimklError::HttpStatus(404)));
+    }
+
+    #[test]
+    #[should_panic(expected = "client bug")]
+    fn test_batch_ratings_panic() {
+        let limiter = Mutex::new(RateLimiter::new(100, Duration::from_secs(1)));
+        let ids = (1..=4).map(|id| MediaIds::new().with_simkl(id));
+        batch_ratings(ids, &[RatingField::Simkl], 2, &limiter, |_| {
+            panic!("client bug");
+        });
+    }
+}
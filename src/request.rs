@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-use crate::{Episode, MediaType, API_URL};
+use crate::{error::SimklError, search::is_url_query, Episode, MediaType, API_URL};
 
 #[derive(Debug, Clone)]
 pub enum HttpMethod {
@@ -76,19 +76,38 @@ pub trait SimklRequest {
     fn headers(&self) -> Vec<(String, String)> {
         Vec::new()
     }
+    /// Check the parameters before sending the request, the API does not always report wrong values
+    fn validate(&self) -> crate::error::Result<()> {
+        Ok(())
+    }
+
+    /// Validate the request and gather everything needed to send it with any HTTP client
+    fn prepare(&self) -> crate::error::Result<PreparedRequest> {
+        self.validate()?;
+        let method = match self.method() {
+            "POST" => HttpMethod::POST,
+            "PUT" => HttpMethod::PUT,
+            "DELETE" => HttpMethod::DELETE,
+            _ => HttpMethod::GET,
+        };
+        let mut request = PreparedRequest::new(method, Url::parse(&self.build_url())?);
+        for (key, value) in self.headers() {
+            request = request.with_header(key, value);
+        }
+        if let Some(body) = self.body() {
+            request = request.with_raw_body(body, "application/json");
+        }
+        Ok(request)
+    }
 
     fn build_url(&self) -> String {
-        let mut url = format!("{}{}", API_URL, self.endpoint());
+        let mut url = Url::parse(API_URL).expect("API_URL is a valid URL");
+        url.set_path(&self.endpoint());
         let params = self.query_params();
         if !params.is_empty() {
-            url.push('?');
-            let param_strings: Vec<String> = params
-                .into_iter()
-                .map(|(k, v)| format!("{}={}", urlencoding::encode(&k), urlencoding::encode(&v)))
-                .collect();
-            url.push_str(&param_strings.join("&"));
+            url.query_pairs_mut().extend_pairs(params);
         }
-        url
+        url.into()
     }
 }
 
@@ -135,6 +154,11 @@ impl SearchRequest {
         self.extended = Some(extended.into());
         self
     }
+
+    /// `q` is a URL pointing to media (IMDb, Letterboxd, ...) instead of keywords
+    pub fn is_url_query(&self) -> bool {
+        is_url_query(&self.q)
+    }
 }
 
 impl SimklRequest for SearchRequest {
@@ -144,12 +168,14 @@ impl SimklRequest for SearchRequest {
             Some(MediaType::Show) => "/search/tv".to_string(),
             Some(MediaType::Anime) => "/search/anime".to_string(),
             Some(MediaType::Episode) => "/search/episode".to_string(),
+            // When using URLs, type can be any
+            None if self.is_url_query() => "/search/movie".to_string(),
             None => "/search".to_string(),
         }
     }
 
     fn query_params(&self) -> Vec<(String, String)> {
-        let mut params = vec![("q".to_string(), self.q.clone())];
+        let mut params = vec![("q".to_string(), self.q.trim().to_string())];
 
         if let Some(limit) = self.limit {
             params.push(("limit".to_string(), limit.to_string()));
@@ -165,6 +191,28 @@ impl SimklRequest for SearchRequest {
 
         params
     }
+
+    fn validate(&self) -> crate::error::Result<()> {
+        if self.q.trim().is_empty() {
+            return Err(SimklError::InvalidParameters(
+                "search term is empty".to_string(),
+            ));
+        }
+        if self.r#type.is_none() && !self.is_url_query() {
+            return Err(SimklError::InvalidParameters(
+                "type is required for text keywords".to_string(),
+            ));
+        }
+        if let Some(page) = self.page {
+            if page > 20 {
+                return Err(SimklError::InvalidParameters(format!(
+                    "page limit is 20, got {}",
+                    page
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
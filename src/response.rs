@@ -28,6 +28,15 @@ impl SimklResponse {
         Ok(serde_json::from_str(&self.body)?)
     }
 
+    /// Turn an error status code into `SimklError::HttpStatus`
+    pub fn error_for_status(self) -> crate::error::Result<Self> {
+        if self.is_success() {
+            Ok(self)
+        } else {
+            Err(crate::error::SimklError::HttpStatus(self.status_code))
+        }
+    }
+
     pub fn is_success(&self) -> bool {
         (200..300).contains(&self.status_code)
     }
@@ -0,0 +1,614 @@
+//! Checkin and scrobble: tell SIMKL what is being watched, the item is added to the history once finished.
+//!
+//! ```text
+//! POST https://api.simkl.com/checkin
+//! POST https://api.simkl.com/scrobble/start
+//! POST https://api.simkl.com/scrobble/pause
+//! POST https://api.simkl.com/scrobble/stop
+//! ```
+//!
+//! Both answer `409 Conflict` when something is already being watched, it is reported as
+//! `SimklError::AlreadyCheckedIn`.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Result, SimklError},
+    request::SimklRequest,
+    response::SimklResponse,
+    sync::LibraryMedia,
+    MediaIds, MediaType,
+};
+
+/// Movie, episode of a show or episode of an anime being watched
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaybackItem {
+    pub media_type: MediaType,
+    pub ids: MediaIds,
+    /// Required for shows
+    pub season: Option<u16>,
+    /// Required for shows, anime without episode are anime movies
+    pub episode: Option<u16>,
+}
+
+impl PlaybackItem {
+    pub fn movie(ids: MediaIds) -> Self {
+        Self {
+            media_type: MediaType::Movie,
+            ids,
+            season: None,
+            episode: None,
+        }
+    }
+
+    /// Episode of a show, `ids` are the ids of the show
+    pub fn episode(ids: MediaIds, season: u16, episode: u16) -> Self {
+        Self {
+            media_type: MediaType::Show,
+            ids,
+            season: Some(season),
+            episode: Some(episode),
+        }
+    }
+
+    /// Episode of an anime, numbered without season
+    pub fn anime_episode(ids: MediaIds, episode: u16) -> Self {
+        Self {
+            media_type: MediaType::Anime,
+            ids,
+            season: None,
+            episode: Some(episode),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !self.ids.has_any_id() {
+            return Err(SimklError::InvalidParameters(
+                "the watched item needs an id".to_string(),
+            ));
+        }
+        let valid = match self.media_type {
+            MediaType::Movie => self.season.is_none() && self.episode.is_none(),
+            MediaType::Show => self.season.is_some() && self.episode.is_some(),
+            MediaType::Anime => self.season.is_none(),
+            MediaType::Episode => false,
+        };
+        if !valid {
+            return Err(SimklError::InvalidParameters(format!(
+                "invalid season or episode for a {}",
+                self.media_type
+            )));
+        }
+        Ok(())
+    }
+
+    fn body(&self, progress: Option<f32>) -> PlaybackBody<'_> {
+        let media = Some(PlaybackMedia { ids: &self.ids });
+        PlaybackBody {
+            progress,
+            movie: media.filter(|_| self.media_type == MediaType::Movie),
+            show: media.filter(|_| self.media_type == MediaType::Show),
+            anime: media.filter(|_| self.media_type == MediaType::Anime),
+            episode: self.episode.map(|number| PlaybackEpisode {
+                season: self.season,
+                number,
+                title: None,
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize)]
+struct PlaybackMedia<'a> {
+    ids: &'a MediaIds,
+}
+
+#[derive(Serialize)]
+struct PlaybackBody<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    movie: Option<PlaybackMedia<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    show: Option<PlaybackMedia<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    anime: Option<PlaybackMedia<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    episode: Option<PlaybackEpisode>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaybackEpisode {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub season: Option<u16>,
+    pub number: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Check into a movie or an episode, it is added to the history once its runtime has elapsed. The response is a
+/// `user::CheckinResponse`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckinRequest {
+    pub item: PlaybackItem,
+}
+
+impl CheckinRequest {
+    pub fn new(item: PlaybackItem) -> Self {
+        Self { item }
+    }
+}
+
+impl SimklRequest for CheckinRequest {
+    fn endpoint(&self) -> String {
+        "/checkin".to_string()
+    }
+
+    fn method(&self) -> &'static str {
+        "POST"
+    }
+
+    fn body(&self) -> Option<String> {
+        serde_json::to_string(&self.item.body(None)).ok()
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        vec![("Content-Type".to_string(), "application/json".to_string())]
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.item.validate()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScrobbleAction {
+    Start,
+    Pause,
+    Stop,
+}
+
+impl ScrobbleAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScrobbleAction::Start => "start",
+            ScrobbleAction::Pause => "pause",
+            ScrobbleAction::Stop => "stop",
+        }
+    }
+}
+
+impl fmt::Display for ScrobbleAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Report the playback of a movie or an episode, the response is a `ScrobbleResponse`. Stopping past 80% adds the
+/// item to the history, under 80% it is paused.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrobbleRequest {
+    pub action: ScrobbleAction,
+    pub item: PlaybackItem,
+    /// Percentage watched, from 0 to 100
+    pub progress: f32,
+}
+
+impl ScrobbleRequest {
+    pub fn new(action: ScrobbleAction, item: PlaybackItem, progress: f32) -> Self {
+        Self {
+            action,
+            item,
+            progress,
+        }
+    }
+
+    pub fn start(item: PlaybackItem, progress: f32) -> Self {
+        Self::new(ScrobbleAction::Start, item, progress)
+    }
+
+    pub fn pause(item: PlaybackItem, progress: f32) -> Self {
+        Self::new(ScrobbleAction::Pause, item, progress)
+    }
+
+    pub fn stop(item: PlaybackItem, progress: f32) -> Self {
+        Self::new(ScrobbleAction::Stop, item, progress)
+    }
+}
+
+impl SimklRequest for ScrobbleRequest {
+    fn endpoint(&self) -> String {
+        format!("/scrobble/{}", self.action)
+    }
+
+    fn method(&self) -> &'static str {
+        "POST"
+    }
+
+    fn body(&self) -> Option<String> {
+        serde_json::to_string(&self.item.body(Some(self.progress))).ok()
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        vec![("Content-Type".to_string(), "application/json".to_string())]
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(0.0..=100.0).contains(&self.progress) {
+            return Err(SimklError::InvalidParameters(format!(
+                "progress must be between 0 and 100, got {}",
+                self.progress
+            )));
+        }
+        self.item.validate()
+    }
+}
+
+/// What the server did with a scrobble
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrobbleResult {
+    Start,
+    Pause,
+    /// Stopped past the threshold, added to the history
+    Scrobble,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ScrobbleResponse {
+    pub id: Option<u64>,
+    pub action: ScrobbleResult,
+    #[serde(default)]
+    pub progress: f32,
+    pub movie: Option<LibraryMedia>,
+    pub show: Option<LibraryMedia>,
+    pub anime: Option<LibraryMedia>,
+    pub episode: Option<PlaybackEpisode>,
+}
+
+impl ScrobbleResponse {
+    pub fn from_response(response: SimklResponse) -> Result<Self> {
+        parse_playback_response(response)
+    }
+}
+
+/// Body of the `409 Conflict` responses
+#[derive(Deserialize)]
+struct Conflict {
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// Parse a checkin or scrobble response, a conflict becomes `SimklError::AlreadyCheckedIn`
+pub(crate) fn parse_playback_response<T: serde::de::DeserializeOwned>(
+    response: SimklResponse,
+) -> Result<T> {
+    if response.status_code == 409 {
+        let expires_at = serde_json::from_str::<Conflict>(&response.body)
+            .ok()
+            .and_then(|c| c.expires_at);
+        return Err(SimklError::AlreadyCheckedIn(expires_at));
+    }
+    response.error_for_status()?.json()
+}
+
+/// Event reported by a media player, with the playback position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlayerEvent {
+    Play(Duration),
+    Pause(Duration),
+    Seek(Duration),
+    /// Periodic position update while playing
+    Tick(Duration),
+    Stop(Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    Idle,
+    Playing,
+    Paused,
+    /// Progress crossed the threshold, the item was scrobbled
+    Watched,
+    Stopped,
+}
+
+/// Turn player events into scrobble requests. Send the returned requests in order, the session does no IO.
+///
+/// * `start` when playback starts or resumes, and once seeks settle so SIMKL knows the new position
+/// * `pause` when paused
+/// * `stop` when the progress crosses the threshold (the item is added to the history), or when the player stops past it
+/// * `pause` when the player stops before the threshold, so SIMKL keeps the progress without adding to the history
+#[derive(Debug, Clone)]
+pub struct ScrobbleSession {
+    item: PlaybackItem,
+    runtime: Duration,
+    threshold: f32,
+    seek_debounce: Duration,
+    state: SessionState,
+    position: Duration,
+    /// Last seek not reported yet
+    pending_seek: Option<Instant>,
+}
+
+impl ScrobbleSession {
+    pub fn new(item: PlaybackItem, runtime: Duration) -> Self {
+        Self {
+            item,
+            runtime,
+            threshold: 80.0,
+            seek_debounce: Duration::from_secs(2),
+            state: SessionState::Idle,
+            position: Duration::ZERO,
+            pending_seek: None,
+        }
+    }
+
+    /// Percentage from which the item is watched and a stop is sent. SIMKL only adds the item to the history from
+    /// 80%, so values outside 80..=100 (and NaN) are ignored.
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        if (80.0..=100.0).contains(&threshold) {
+            self.threshold = threshold;
+        }
+        self
+    }
+
+    /// Time without seek before reporting the new position
+    pub fn with_seek_debounce(mut self, seek_debounce: Duration) -> Self {
+        self.seek_debounce = seek_debounce;
+        self
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// Percentage watched at the last event, rounded to 2 decimals
+    pub fn progress(&self) -> f32 {
+        if self.runtime.is_zero() {
+            return 0.0;
+        }
+        let progress = self.position.as_secs_f64() / self.runtime.as_secs_f64() * 100.0;
+        ((progress * 100.0).round() / 100.0).clamp(0.0, 100.0) as f32
+    }
+
+    pub fn is_watched(&self) -> bool {
+        self.state == SessionState::Watched
+    }
+
+    fn request(&self, action: ScrobbleAction) -> ScrobbleRequest {
+        ScrobbleRequest::new(action, self.item.clone(), self.progress())
+    }
+
+    /// Update the session with a player event received at `now`, returns the request to send if any
+    pub fn handle(&mut self, event: PlayerEvent, now: Instant) -> Option<ScrobbleRequest> {
+        if matches!(self.state, SessionState::Watched | SessionState::Stopped) {
+            return None;
+        }
+        match event {
+            PlayerEvent::Play(position) => {
+                self.position = position;
+                if self.state == SessionState::Playing {
+                    if self.is_seeking(now) {
+                        return None;
+                    }
+                    return self.check_threshold();
+                }
+                self.pending_seek = None;
+                self.state = SessionState::Playing;
+                self.check_threshold()
+                    .or_else(|| Some(self.request(ScrobbleAction::Start)))
+            }
+            PlayerEvent::Pause(position) => {
+                self.position = position;
+                self.pending_seek = None;
+                if self.state != SessionState::Playing {
+                    return None;
+                }
+                self.state = SessionState::Paused;
+                Some(self.request(ScrobbleAction::Pause))
+            }
+            PlayerEvent::Seek(position) => {
+                self.position = position;
+                if self.state == SessionState::Playing {
+                    self.pending_seek = Some(now);
+                }
+                None
+            }
+            PlayerEvent::Tick(position) => {
+                self.position = position;
+                if self.state != SessionState::Playing || self.is_seeking(now) {
+                    return None;
+                }
+                if let Some(request) = self.check_threshold() {
+                    return Some(request);
+                }
+                self.pending_seek
+                    .take()
+                    .map(|_| self.request(ScrobbleAction::Start))
+            }
+            PlayerEvent::Stop(position) => {
+                self.position = position;
+                if self.state == SessionState::Idle {
+                    self.state = SessionState::Stopped;
+                    return None;
+                }
+                self.check_threshold().or_else(|| {
+                    self.state = SessionState::Stopped;
+                    Some(self.request(ScrobbleAction::Pause))
+                })
+            }
+        }
+    }
+
+    /// A seek was made less than `seek_debounce` ago, the position may still move
+    fn is_seeking(&self, now: Instant) -> bool {
+        self.pending_seek
+            .is_some_and(|seek| now.duration_since(seek) < self.seek_debounce)
+    }
+
+    fn check_threshold(&mut self) -> Option<ScrobbleRequest> {
+        if self.progress() < self.threshold {
+            return None;
+        }
+        self.state = SessionState::Watched;
+        self.pending_seek = None;
+        Some(self.request(ScrobbleAction::Stop))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::CheckinResponse;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_scrobble_request() {
+        let item = PlaybackItem::episode(MediaIds::new().with_simkl(17465), 1, 2);
+        let request = ScrobbleRequest::start(item.clone(), 12.5);
+        assert!(request.validate().is_ok());
+        assert_eq!(request.build_url(), "https://api.simkl.com/scrobble/start");
+        assert_eq!(
+            request.body().unwrap(),
+            r#"{"progress":12.5,"show":{"ids":{"simkl":17465}},"episode":{"season":1,"number":2}}"#
+        );
+        assert!(ScrobbleRequest::stop(item, 120.0).validate().is_err());
+        let movie = PlaybackItem {
+            season: Some(1),
+            ..PlaybackItem::movie(MediaIds::new().with_simkl(1))
+        };
+        assert!(CheckinRequest::new(movie).validate().is_err());
+
+        let body = r#"{"id":3,"action":"scrobble","progress":95,"anime":{"title":"One Piece","ids":{"simkl":38636}},"episode":{"number":1000}}"#;
+        let response = SimklResponse::new(201, HashMap::new(), body.to_string());
+        let response = ScrobbleResponse::from_response(response).unwrap();
+        assert_eq!(response.action, ScrobbleResult::Scrobble);
+        assert_eq!(response.episode.unwrap().number, 1000);
+
+        let body = r#"{"watched_at":"2024-03-01T20:00:00Z","expires_at":"2024-03-01T21:00:00Z","show":{"title":"Breaking Bad","year":2008,"ids":{"simkl":17465}},"episode":{"season":1,"number":2}}"#;
+        let response = SimklResponse::new(201, HashMap::new(), body.to_string());
+        let checkin = CheckinResponse::from_response(response).unwrap();
+        assert!(checkin.expires_at > checkin.watched_at);
+        assert_eq!(checkin.show.unwrap().year, Some(2008));
+
+        let body = r#"{"watched_at":"2024-03-01T20:00:00Z","expires_at":"2024-03-01T21:00:00Z"}"#;
+        let response = SimklResponse::new(409, HashMap::new(), body.to_string());
+        assert!(matches!(
+            CheckinResponse::from_response(response),
+            Err(SimklError::AlreadyCheckedIn(Some(_)))
+        ));
+    }
+
+    #[test]
+    fn test_scrobble_session() {
+        let item = PlaybackItem::movie(MediaIds::new().with_simkl(1));
+        let mut session = ScrobbleSession::new(item, Duration::from_secs(100));
+        let t0 = Instant::now();
+        let at = |secs| t0 + Duration::from_secs(secs);
+        let pos = Duration::from_secs;
+        let action = |r: Option<ScrobbleRequest>| r.map(|r| (r.action, r.progress));
+
+        assert_eq!(
+            action(session.handle(PlayerEvent::Tick(pos(1)), at(1))),
+            None
+        );
+        assert_eq!(
+            action(session.handle(PlayerEvent::Play(pos(0)), at(1))),
+            Some((ScrobbleAction::Start, 0.0))
+        );
+        // rapid seeks are reported once, when they settle
+        session.handle(PlayerEvent::Seek(pos(30)), at(2));
+        session.handle(PlayerEvent::Seek(pos(50)), at(3));
+        assert_eq!(
+            action(session.handle(PlayerEvent::Tick(pos(51)), at(4))),
+            None
+        );
+        assert_eq!(
+            action(session.handle(PlayerEvent::Tick(pos(52)), at(5))),
+            Some((ScrobbleAction::Start, 52.0))
+        );
+        assert_eq!(
+            action(session.handle(PlayerEvent::Pause(pos(60)), at(6))),
+            Some((ScrobbleAction::Pause, 60.0))
+        );
+        assert_eq!(
+            action(session.handle(PlayerEvent::Pause(pos(60)), at(7))),
+            None
+        );
+        session.handle(PlayerEvent::Play(pos(60)), at(8));
+        assert_eq!(
+            action(session.handle(PlayerEvent::Tick(pos(80)), at(28))),
+            Some((ScrobbleAction::Stop, 80.0))
+        );
+        assert!(session.is_watched());
+        assert_eq!(
+            action(session.handle(PlayerEvent::Stop(pos(90)), at(38))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_seek_through_threshold() {
+        let item = PlaybackItem::movie(MediaIds::new().with_simkl(1));
+        let mut session = ScrobbleSession::new(item, Duration::from_secs(100));
+        let t0 = Instant::now();
+        let at = |millis| t0 + Duration::from_millis(millis);
+        let pos = Duration::from_secs;
+
+        session.handle(PlayerEvent::Play(pos(0)), at(0));
+        // scrubbing past 90% and back is not a watch
+        session.handle(PlayerEvent::Seek(pos(90)), at(1000));
+        assert_eq!(session.handle(PlayerEvent::Tick(pos(90)), at(1500)), None);
+        assert_eq!(session.handle(PlayerEvent::Play(pos(90)), at(1600)), None);
+        session.handle(PlayerEvent::Seek(pos(10)), at(2000));
+        let request = session.handle(PlayerEvent::Tick(pos(11)), at(4500));
+        assert_eq!(request.map(|r| r.action), Some(ScrobbleAction::Start));
+        assert_eq!(session.state(), SessionState::Playing);
+
+        let session =
+            ScrobbleSession::new(PlaybackItem::movie(MediaIds::new().with_simkl(1)), pos(1))
+                .with_threshold(f32::NAN);
+        assert_eq!(session.threshold, 80.0);
+    }
+
+    #[test]
+    fn test_stop_and_threshold() {
+        let item = PlaybackItem::movie(MediaIds::new().with_simkl(1));
+        let t0 = Instant::now();
+        let pos = Duration::from_secs;
+        let stop_at = |threshold, position| {
+            let mut session =
+                ScrobbleSession::new(item.clone(), pos(100)).with_threshold(threshold);
+            session.handle(PlayerEvent::Play(pos(0)), t0);
+            let request = session.handle(PlayerEvent::Stop(pos(position)), t0);
+            (request.map(|r| r.action), session.state())
+        };
+
+        // SIMKL would add it to the history at 85%, before the threshold
+        assert_eq!(
+            stop_at(90.0, 85),
+            (Some(ScrobbleAction::Pause), SessionState::Stopped)
+        );
+        assert_eq!(
+            stop_at(90.0, 90),
+            (Some(ScrobbleAction::Stop), SessionState::Watched)
+        );
+        assert_eq!(
+            stop_at(80.0, 79),
+            (Some(ScrobbleAction::Pause), SessionState::Stopped)
+        );
+        // SIMKL only scrobbles from 80%, a lower threshold would report a pause as watched
+        let session = ScrobbleSession::new(item.clone(), pos(100)).with_threshold(70.0);
+        assert_eq!(session.threshold, 80.0);
+        assert_eq!(
+            stop_at(70.0, 75),
+            (Some(ScrobbleAction::Pause), SessionState::Stopped)
+        );
+    }
+}
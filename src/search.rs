@@ -1,16 +1,127 @@
-use crate::{anime::AnimeGenre, movie::MovieGenre, show::TvGenre, Extended, API_URL};
+use serde::{
+    de::{value::StrDeserializer, IntoDeserializer},
+    Deserialize, Deserializer, Serialize,
+};
+use url::Url;
 
+use crate::{
+    anime::AnimeType,
+    error::{Result, SimklError},
+    get_extended_parameter,
+    request::SimklRequest,
+    Extended, Genre, MediaIds, MediaType, Rating, StandardMediaObject, API_URL,
+};
+
+/// Identify a movie or an episode from a file name, the response is a `FileMatch`.
+///
+/// ```text
+/// POST https://api.simkl.com/search/file
+/// ```
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
 pub struct FindByFilePayload {
-    // Try to find the file from the filename, example:
+    /// Try to find the file from the filename, example:
     /// `Were.The.Fugawis.S01E01E02.WS.DSR.x264-NY2.mkv`
     pub file: String,
     /// Some filenames consist of 2 or more parts. If you want to get info about second part for example you can pass 2 to this parameter
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub part: Option<u8>,
+    /// Hash of the file, helps matching files that have been renamed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl FindByFilePayload {
+    pub fn new(file: impl Into<String>) -> Self {
+        Self {
+            file: file.into(),
+            part: None,
+            hash: None,
+        }
+    }
+
+    pub fn with_part(mut self, part: u8) -> Self {
+        self.part = Some(part);
+        self
+    }
+
+    pub fn with_hash(mut self, hash: impl Into<String>) -> Self {
+        self.hash = Some(hash.into());
+        self
+    }
+}
+
+impl SimklRequest for FindByFilePayload {
+    fn endpoint(&self) -> String {
+        "/search/file".to_string()
+    }
+
+    fn method(&self) -> &'static str {
+        "POST"
+    }
+
+    fn body(&self) -> Option<String> {
+        serde_json::to_string(self).ok()
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        vec![("Content-Type".to_string(), "application/json".to_string())]
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.file.trim().is_empty() {
+            return Err(SimklError::InvalidParameters(
+                "file name is empty".to_string(),
+            ));
+        }
+        if self.part == Some(0) {
+            return Err(SimklError::InvalidParameters(
+                "part starts at 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 /// Find by file URL, use `FindByFilePayload` with this
 pub const FIND_BY_FILE_URL: &str = "https://api.simkl.com/search/file";
 
+/// Episode recognized from a file name
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct FileEpisode {
+    pub title: Option<String>,
+    /// Missing for anime which have no seasons
+    pub season: Option<u16>,
+    pub episode: u16,
+    /// `true` when the file contains several episodes (e.g. `S01E01E02`)
+    pub multipart: Option<bool>,
+    #[serde(default)]
+    pub ids: MediaIds,
+}
+
+/// Response of the find by file request
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FileMatch {
+    Movie {
+        movie: StandardMediaObject,
+    },
+    Episode {
+        episode: FileEpisode,
+        /// The TV show or the anime the episode belongs to
+        #[serde(alias = "anime")]
+        show: StandardMediaObject,
+    },
+}
+
+impl FileMatch {
+    pub fn ids(&self) -> &MediaIds {
+        match self {
+            FileMatch::Movie { movie } => &movie.ids,
+            FileMatch::Episode { show, .. } => &show.ids,
+        }
+    }
+}
+
 /// Struct used to build the payload of a search by ID
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct IdLookup {
@@ -36,89 +147,235 @@ pub struct IdLookup {
     pub year: Option<u16>,
 }
 
-pub fn get_search_by_id_request(payload: IdLookup, client_id: String) -> String {
-    let mut result = String::from(API_URL);
-    result.push_str("search/id?client_id=");
-    result.push_str(&client_id);
-    if let Some(id) = payload.simkl {
-        result.push_str("&simkl=");
-        result.push_str(&id.to_string());
-    }
-    if let Some(id) = payload.hulu {
-        result.push_str("&hulu=");
-        result.push_str(&id.to_string());
+impl IdLookup {
+    fn has_criteria(&self) -> bool {
+        self.simkl.is_some()
+            || self.hulu.is_some()
+            || self.netflix.is_some()
+            || self.mal.is_some()
+            || self.tvdb.is_some()
+            || self.tmdb.is_some()
+            || self.imdb.is_some()
+            || self.anidb.is_some()
+            || self.crunchyroll.is_some()
+            || self.anilist.is_some()
+            || self.kitsu.is_some()
+            || self.livechart.is_some()
+            || self.anisearch.is_some()
+            || self.animeplanet.is_some()
+            || self.title.is_some()
     }
-    if let Some(id) = payload.netflix {
-        result.push_str("&netflix=");
-        result.push_str(&id.to_string());
-    }
-    if let Some(id) = payload.mal {
-        result.push_str("&mal=");
-        result.push_str(&id.to_string());
-    }
-    if let Some(id) = payload.tvdb {
-        result.push_str("&tvdb=");
-        result.push_str(&id.to_string());
-    }
-    if let Some(id) = payload.tmdb {
-        result.push_str("&tmdb=");
-        result.push_str(&id.to_string());
-    }
-    if let Some(id) = payload.imdb {
-        result.push_str("&imdb=");
-        result.push_str(&id);
-    }
-    if let Some(id) = payload.anidb {
-        result.push_str("&anidb=");
-        result.push_str(&id.to_string());
+}
+
+/// Find items by ID or title, the response is a list of `IdLookupResult`.
+impl SimklRequest for IdLookup {
+    fn endpoint(&self) -> String {
+        "/search/id".to_string()
     }
-    if let Some(id) = payload.crunchyroll {
-        result.push_str("&crunchyroll=");
-        result.push_str(&id.to_string());
+
+    fn query_params(&self) -> Vec<(String, String)> {
+        let ids = [
+            ("simkl", self.simkl),
+            ("hulu", self.hulu),
+            ("netflix", self.netflix),
+            ("mal", self.mal),
+            ("tvdb", self.tvdb),
+            ("tmdb", self.tmdb),
+        ];
+        let mut params: Vec<(String, String)> = ids
+            .into_iter()
+            .filter_map(|(k, v)| v.map(|id| (k.to_string(), id.to_string())))
+            .collect();
+        if let Some(ref id) = self.imdb {
+            params.push(("imdb".to_string(), id.clone()));
+        }
+        let ids = [
+            ("anidb", self.anidb),
+            ("crunchyroll", self.crunchyroll),
+            ("anilist", self.anilist),
+            ("kitsu", self.kitsu),
+            ("livechart", self.livechart),
+            ("anisearch", self.anisearch),
+            ("animeplanet", self.animeplanet),
+        ];
+        params.extend(
+            ids.into_iter()
+                .filter_map(|(k, v)| v.map(|id| (k.to_string(), id.to_string()))),
+        );
+        if let Some(ref t) = self.r#type {
+            params.push(("type".to_string(), t.clone()));
+        }
+        if let Some(ref title) = self.title {
+            params.push(("title".to_string(), title.clone()));
+        }
+        if let Some(year) = self.year {
+            params.push(("year".to_string(), year.to_string()));
+        }
+        params
     }
-    if let Some(id) = payload.anilist {
-        result.push_str("&anilist=");
-        result.push_str(&id.to_string());
+
+    fn validate(&self) -> Result<()> {
+        if !self.has_criteria() {
+            return Err(SimklError::InvalidParameters(
+                "at least one id or a title is required".to_string(),
+            ));
+        }
+        if let Some(ref t) = self.r#type {
+            if t != "show" && t != "movie" {
+                return Err(SimklError::InvalidParameters(format!(
+                    "type must be show or movie, got {}",
+                    t
+                )));
+            }
+        }
+        Ok(())
     }
-    if let Some(id) = payload.kitsu {
-        result.push_str("&kitsu=");
-        result.push_str(&id.to_string());
+}
+
+/// Item returned by `IdLookup`
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct IdLookupResult {
+    /// `movie`, `show` or `anime`
+    pub r#type: Option<String>,
+    pub poster: Option<String>,
+    #[serde(flatten)]
+    pub media: StandardMediaObject,
+}
+
+/// Same as the `IdLookup` request for callers that pass the `client_id` in the URL instead of the headers.
+pub fn get_search_by_id_request(payload: IdLookup, client_id: String) -> Result<String> {
+    payload.validate()?;
+    let mut url = Url::parse(API_URL)?;
+    url.set_path(&payload.endpoint());
+    url.query_pairs_mut()
+        .append_pair("client_id", &client_id)
+        .extend_pairs(payload.query_params());
+    Ok(url.into())
+}
+
+/// Tell whether a search term is a URL pointing to media (IMDb, Letterboxd, ...) rather than keywords
+pub fn is_url_query(q: &str) -> bool {
+    let q = q.trim();
+    (q.starts_with("https://") || q.starts_with("http://")) && Url::parse(q).is_ok()
+}
+
+/// Classification of what a user typed in a search box, see `parse_query`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParsedQuery {
+    /// Keywords, with the year found at the end if any (`john wick 2014`)
+    Text { query: String, year: Option<u16> },
+    /// URL of a known site without an id usable by `/search/id` (e.g. Letterboxd), can be used as `q` of a search
+    Url(String),
+    /// Ids found in a URL or typed as is (`tt1234567`), use it with `/search/id`
+    Ids(IdLookup),
+}
+
+fn is_imdb_id(s: &str) -> bool {
+    s.len() > 2 && s.starts_with("tt") && s[2..].bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Parse the leading number of a path segment such as `245891-john-wick`
+fn leading_id(segment: &str) -> Option<u32> {
+    let digits: String = segment.chars().take_while(|c| c.is_ascii_digit()).collect();
+    match segment[digits.len()..].chars().next() {
+        None | Some('-') => digits.parse().ok(),
+        _ => None,
     }
-    if let Some(id) = payload.livechart {
-        result.push_str("&livechart=");
-        result.push_str(&id.to_string());
+}
+
+/// Extract ids from a media URL, `None` when the site is not supported
+fn parse_media_url(url: &Url) -> Option<ParsedQuery> {
+    let host = url.host_str()?.to_lowercase();
+    let host = host
+        .strip_prefix("www.")
+        .or_else(|| host.strip_prefix("m."))
+        .unwrap_or(&host);
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    let mut lookup = IdLookup::default();
+    match (host, segments.as_slice()) {
+        ("imdb.com", ["title", id, ..]) if is_imdb_id(id) => lookup.imdb = Some(id.to_string()),
+        ("themoviedb.org", ["movie", id, ..]) => {
+            lookup.tmdb = leading_id(id);
+            lookup.r#type = Some(String::from("movie"));
+        }
+        ("themoviedb.org", ["tv", id, ..]) => {
+            lookup.tmdb = leading_id(id);
+            lookup.r#type = Some(String::from("show"));
+        }
+        ("myanimelist.net", ["anime", id, ..]) => lookup.mal = leading_id(id),
+        ("anilist.co", ["anime", id, ..]) => lookup.anilist = leading_id(id),
+        ("kitsu.io" | "kitsu.app", ["anime", id, ..]) => lookup.kitsu = leading_id(id),
+        ("thetvdb.com", _) => {
+            lookup.tvdb = url
+                .query_pairs()
+                .find(|(k, _)| k == "id" || k == "seriesid")
+                .and_then(|(_, v)| v.parse().ok())
+                .or_else(|| match segments.as_slice() {
+                    ["series", id, ..] | ["dereferrer", "series", id, ..] => id.parse().ok(),
+                    _ => None,
+                });
+        }
+        ("simkl.com", ["tv" | "movies" | "anime", id, ..]) => lookup.simkl = leading_id(id),
+        ("letterboxd.com", ..) => {}
+        _ => return None,
     }
-    if let Some(id) = payload.anisearch {
-        result.push_str("&anisearch=");
-        result.push_str(&id.to_string());
+    if lookup.has_criteria() {
+        Some(ParsedQuery::Ids(lookup))
+    } else {
+        Some(ParsedQuery::Url(url.to_string()))
     }
-    if let Some(id) = payload.animeplanet {
-        result.push_str("&animeplanet=");
-        result.push_str(&id.to_string());
+}
+
+/// Classify user input so it can be routed to `/search/id` when possible:
+/// * a URL of IMDb, TMDB, MyAnimeList, AniList, TVDB, Kitsu or SIMKL gives the matching `IdLookup` field,
+/// * a Letterboxd URL (or any supported site URL without id) is kept as a URL to search with,
+/// * an IMDb id such as `tt1234567` gives an `IdLookup`,
+/// * anything else is free text, with an optional trailing year (`john wick 2014`).
+pub fn parse_query(input: &str) -> ParsedQuery {
+    let input = input.trim();
+    if is_imdb_id(input) {
+        return ParsedQuery::Ids(IdLookup {
+            imdb: Some(input.to_string()),
+            ..Default::default()
+        });
     }
-    if let Some(t) = payload.r#type {
-        result.push_str("&type=");
-        result.push_str(&t);
+    if !input.contains(char::is_whitespace) && input.contains('.') && input.contains('/') {
+        let url = if is_url_query(input) {
+            Url::parse(input).ok()
+        } else {
+            Url::parse(&format!("https://{}", input)).ok()
+        };
+        if let Some(parsed) = url.as_ref().and_then(parse_media_url) {
+            return parsed;
+        }
     }
-    if let Some(t) = payload.title {
-        result.push_str("&title=");
-        result.push_str(&t);
+
+    let mut words: Vec<&str> = input.split_whitespace().collect();
+    let year = match words.last() {
+        Some(last) if words.len() > 1 && last.len() == 4 => {
+            last.parse().ok().filter(|y| (1870..=2100).contains(y))
+        }
+        _ => None,
+    };
+    if year.is_some() {
+        words.pop();
     }
-    if let Some(y) = payload.year {
-        result.push_str("&year=");
-        result.push_str(&y.to_string());
+    ParsedQuery::Text {
+        query: words.join(" "),
+        year,
     }
-    result
 }
 
-/// Search items by title, sorted by relevance (what people search most). This method will respond with
-/// `StandardMediaObject` + additional fields if `extended` parameter passed.
+/// Search items by title, sorted by relevance (what people search most). This method will respond with a list of
+/// `SearchResult`, with additional fields if `extended` parameter passed.
 ///
 /// For `movies` or `anime` with movie type, tmdb id points to the movies section on TMDB site, otherwise to the TV
-/// section.
+/// section, see `SearchResult::tmdb_section`.
 ///
-/// Items with `endpoint_type = anime` has additional anime `type` key, see possible values for the `anime_type` key in
-/// `StandardMediaObject`.
+/// Items with `endpoint_type = anime` has additional anime `type` key, see `SearchResult::anime_type`.
 ///
 /// Page limit is 20, max items per page is 50.
 pub fn get_search_request(
@@ -134,93 +391,492 @@ pub fn get_search_request(
     //
     // Examples: `john wick`, `john wick 2014`, `https://www.imdb.com/title/tt2911666/`, `https://letterboxd.com/film/john-wick/`
     q: String,
-) -> String {
-    let mut result = String::from(API_URL);
-    result.push_str("search/type?client_id=");
-    result.push_str(&client_id);
-    result.push_str("&q=");
-    result.push_str(&q);
-    if let Some(t) = r#type {
-        result.push_str("&type=");
-        result.push_str(&t);
-    }
-    // if let Some(ext) = extended {
-    //     result.push_str("&extended=");
-    //     result.push_str(&ext.);
-    // }
-    result
-}
-
-pub struct FindRandomPayload {
-    pub service: String,
-    pub r#type: crate::MediaType, // tv , anime , movie
-    pub movie_genre: MovieGenre,
-    pub tv_genre: TvGenre,
-    pub anime_genre: AnimeGenre,
+) -> Result<String> {
+    let t = match r#type {
+        Some(t) => t,
+        None if is_url_query(&q) => String::from("movie"),
+        None => {
+            return Err(SimklError::InvalidParameters(
+                "type is required for text keywords".to_string(),
+            ))
+        }
+    };
+    if !matches!(t.as_str(), "tv" | "anime" | "movie") {
+        return Err(SimklError::InvalidParameters(format!(
+            "type must be tv, anime or movie, got {}",
+            t
+        )));
+    }
+    let mut url = Url::parse(API_URL)?;
+    url.set_path(&format!("/search/{}", t));
+    url.query_pairs_mut()
+        .append_pair("client_id", &client_id)
+        .append_pair("q", q.trim());
+    if let Some(ext) = extended {
+        let ext = get_extended_parameter(ext)
+            .map_err(|e| SimklError::InvalidParameters(e.to_string()))?;
+        url.query_pairs_mut().append_pair("extended", &ext);
+    }
+    Ok(url.into())
+}
+
+/// Section of the API that returned a search result
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EndpointType {
+    Movies,
+    Tv,
+    Anime,
+}
+
+/// TMDB has separate id spaces for movies and TV shows
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TmdbSection {
+    Movie,
+    Tv,
+}
+
+/// Ratings given with extended search results
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct SearchRatings {
+    pub simkl: Option<Rating>,
+    pub imdb: Option<Rating>,
+    pub mal: Option<Rating>,
+}
+
+/// Item returned by `SearchRequest` and `get_search_request`, for text and URL searches alike
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SearchResult {
+    pub endpoint_type: EndpointType,
+    pub title: String,
+    pub title_romaji: Option<String>,
+    pub year: Option<u16>,
+    /// Poster reference, use it with `images::get_poster_url`
+    pub poster: Option<String>,
+    #[serde(default)]
+    pub ids: MediaIds,
+    /// Only set for anime
+    #[serde(default, rename = "type", deserialize_with = "lenient_anime_type")]
+    anime_type: Option<AnimeType>,
+
+    // Extended fields
+    /// Relative SIMKL URL, example: `/movies/53536/john-wick`
+    pub url: Option<String>,
+    pub rank: Option<u32>,
+    pub ratings: Option<SearchRatings>,
+    /// Show status such as `ended`
+    pub status: Option<String>,
+    pub total_episodes: Option<u32>,
+    #[serde(default)]
+    pub all_titles: Vec<String>,
+    pub overview: Option<String>,
+}
+
+/// The anime `type` is an open list on the API side, unknown values are dropped instead of failing the whole result
+pub(crate) fn lenient_anime_type<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<AnimeType>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    Ok(value.and_then(|v| {
+        let de: StrDeserializer<serde::de::value::Error> = v.as_str().into_deserializer();
+        AnimeType::deserialize(de).ok()
+    }))
+}
+
+impl SearchResult {
+    /// Anime type (tv, ova, movie, ...), `None` for movies and TV shows
+    pub fn anime_type(&self) -> Option<AnimeType> {
+        match self.endpoint_type {
+            EndpointType::Anime => self.anime_type,
+            _ => None,
+        }
+    }
+
+    pub fn media_type(&self) -> MediaType {
+        match self.endpoint_type {
+            EndpointType::Movies => MediaType::Movie,
+            EndpointType::Tv => MediaType::Show,
+            EndpointType::Anime => MediaType::Anime,
+        }
+    }
+
+    /// TMDB section the `tmdb` id points to: movies and movie anime are in the movie section, the rest is in TV
+    pub fn tmdb_section(&self) -> TmdbSection {
+        match (self.endpoint_type, self.anime_type()) {
+            (EndpointType::Movies, _) | (EndpointType::Anime, Some(AnimeType::Movie)) => {
+                TmdbSection::Movie
+            }
+            _ => TmdbSection::Tv,
+        }
+    }
+
+    pub fn tmdb_url(&self) -> Option<String> {
+        let section = match self.tmdb_section() {
+            TmdbSection::Movie => "movie",
+            TmdbSection::Tv => "tv",
+        };
+        self.ids
+            .tmdb
+            .map(|id| format!("https://www.themoviedb.org/{}/{}", section, id))
+    }
+}
+
+/// Maps a media type to the `type` value used by the search endpoints
+fn search_type(media_type: &MediaType) -> Option<&'static str> {
+    match media_type {
+        MediaType::Show => Some("tv"),
+        MediaType::Anime => Some("anime"),
+        MediaType::Movie => Some("movie"),
+        MediaType::Episode => None,
+    }
+}
+
+/// If you want to find random item based on your filters. If Token is passed, wacthed items will be excluded.
+///
+/// Examples:
+/// * `https://api.simkl.com/search/random?type=tv&genre=comedy&rating_from=5&rating_to=10&year_from=2004&year_to=2010&limit=10`
+/// * `https://api.simkl.com/search/random/netflix?rating_from=5&rating_to=10&year_from=2008&year_to=2015&genre=science-fiction`
+///
+/// Response is a list of `RandomItem`.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct RandomRequest {
+    /// Streaming service to pick from, example: `netflix`. Search on SIMKL when empty.
+    pub service: Option<String>,
+    /// Possible values are `Show`, `Anime` or `Movie`. Deduced from the genre if not set.
+    pub r#type: Option<MediaType>,
+    pub genre: Option<Genre>,
     /// Max value is 10. Random search for TV Shows and Movies will be performed using IMDB ratings. Anime are based on
     /// MAL ratings.
     ///
     /// Example: `5`. Default: `1`.
-    pub rating_from: u8,
+    pub rating_from: Option<u8>,
     /// Example: `10`
-    pub rating_to: u8,
+    pub rating_to: Option<u8>,
     /// Maximum rank allowed. Example: `2000`.
-    pub rank_limit: u32,
+    pub rank_limit: Option<u32>,
     pub year_from: Option<u16>,
     pub year_to: Option<u16>,
-    pub limit: u16,
+    /// Max value is 10
+    pub limit: Option<u8>,
 }
 
-impl FindRandomPayload {
-    pub fn to_url_param(&self) -> String {
-        let mut result = String::from(API_URL);
-        result.push_str("&service=");
-        result.push_str(&self.service);
-        // result.push_str("&type=");
-        // result.push_str(&self.r#type);
-        todo!("type, genre");
+impl RandomRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        if let Some(y) = self.year_from {
-            result.push_str("&year_from=");
-            result.push_str(&y.to_string());
-        }
-        if let Some(y) = self.year_to {
-            result.push_str("&year_to=");
-            result.push_str(&y.to_string());
+    pub fn with_service(mut self, service: impl Into<String>) -> Self {
+        self.service = Some(service.into());
+        self
+    }
+
+    pub fn with_type(mut self, media_type: MediaType) -> Self {
+        self.r#type = Some(media_type);
+        self
+    }
+
+    pub fn with_genre(mut self, genre: impl Into<Genre>) -> Self {
+        self.genre = Some(genre.into());
+        self
+    }
+
+    pub fn with_rating(mut self, from: u8, to: u8) -> Self {
+        self.rating_from = Some(from);
+        self.rating_to = Some(to);
+        self
+    }
+
+    pub fn with_years(mut self, from: u16, to: u16) -> Self {
+        self.year_from = Some(from);
+        self.year_to = Some(to);
+        self
+    }
+
+    pub fn with_rank_limit(mut self, rank_limit: u32) -> Self {
+        self.rank_limit = Some(rank_limit);
+        self
+    }
+
+    pub fn with_limit(mut self, limit: u8) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn media_type(&self) -> Option<MediaType> {
+        self.r#type.or_else(|| self.genre.map(|g| g.media_type()))
+    }
+}
+
+impl SimklRequest for RandomRequest {
+    fn endpoint(&self) -> String {
+        match self.service {
+            Some(ref service) => format!("/search/random/{}", service),
+            None => "/search/random".to_string(),
         }
+    }
 
-        result.push_str("&rating_from=");
-        result.push_str(&self.rating_from.to_string());
-        result.push_str("&rating_to=");
-        result.push_str(&self.rating_to.to_string());
+    fn query_params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        if let Some(t) = self.media_type().as_ref().and_then(search_type) {
+            params.push(("type".to_string(), t.to_string()));
+        }
+        if let Some(genre) = self.genre {
+            params.push(("genre".to_string(), genre.as_str().to_string()));
+        }
+        if let Some(rating) = self.rating_from {
+            params.push(("rating_from".to_string(), rating.to_string()));
+        }
+        if let Some(rating) = self.rating_to {
+            params.push(("rating_to".to_string(), rating.to_string()));
+        }
+        if let Some(year) = self.year_from {
+            params.push(("year_from".to_string(), year.to_string()));
+        }
+        if let Some(year) = self.year_to {
+            params.push(("year_to".to_string(), year.to_string()));
+        }
+        if let Some(rank) = self.rank_limit {
+            params.push(("rank_limit".to_string(), rank.to_string()));
+        }
+        if let Some(limit) = self.limit {
+            params.push(("limit".to_string(), limit.to_string()));
+        }
+        params
+    }
 
-        result.push_str("&rank_limit=");
-        result.push_str(&self.rank_limit.to_string());
-        result.push_str("&limit=");
-        result.push_str(&self.limit.to_string());
-        result
+    fn validate(&self) -> Result<()> {
+        if let Some(ref service) = self.service {
+            if service.is_empty() || service.contains('/') {
+                return Err(SimklError::InvalidParameters(format!(
+                    "invalid service: {}",
+                    service
+                )));
+            }
+        }
+        if self.r#type == Some(MediaType::Episode) {
+            return Err(SimklError::InvalidParameters(
+                "random search does not support episodes".to_string(),
+            ));
+        }
+        if let (Some(t), Some(genre)) = (self.r#type, self.genre) {
+            if t != genre.media_type() {
+                return Err(SimklError::InvalidParameters(format!(
+                    "genre {} is not a {} genre",
+                    genre.as_str(),
+                    t
+                )));
+            }
+        }
+        for rating in [self.rating_from, self.rating_to].into_iter().flatten() {
+            if rating > 10 {
+                return Err(SimklError::InvalidParameters(format!(
+                    "rating must be between 0 and 10, got {}",
+                    rating
+                )));
+            }
+        }
+        if let (Some(from), Some(to)) = (self.rating_from, self.rating_to) {
+            if from > to {
+                return Err(SimklError::InvalidParameters(
+                    "rating_from is greater than rating_to".to_string(),
+                ));
+            }
+        }
+        if let (Some(from), Some(to)) = (self.year_from, self.year_to) {
+            if from > to {
+                return Err(SimklError::InvalidParameters(
+                    "year_from is greater than year_to".to_string(),
+                ));
+            }
+        }
+        if let Some(limit) = self.limit {
+            if limit == 0 || limit > 10 {
+                return Err(SimklError::InvalidParameters(format!(
+                    "limit must be between 1 and 10, got {}",
+                    limit
+                )));
+            }
+        }
+        Ok(())
     }
 }
 
-/// if you want to find random item based on your filters. If Token is passed, wacthed items will be excluded.
-///
-/// Examples:
-/// * `https://api.simkl.com/search/random?service=simkl&type=tv&genre=comedy&rating_from=5&rating_to=10&year_from=2004&year_to=2010&limit=10&client_id=***`
-/// * `https://api.simkl.com/search/random/netflix/?rating_from=5&rating_to=10&year_from=2008&year_to=2015&genre=science-fiction&client_id=***`
-/// * `https://api.simkl.com/search/random/?rating_from=5&rating_to=10&year_from=2008&year_to=2015&genre=science-fiction&client_id=***`
-pub fn get_find_random_request(payload: FindRandomPayload, client_id: String) -> String {
-    let mut result = String::from(API_URL);
-    result.push_str("search/random/?client_id=");
-    result.push_str(&client_id);
-    // result.push('&');
-    result.push_str(&payload.to_url_param());
-    result
+/// An item picked by `RandomRequest`
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct RandomItem {
+    #[serde(default)]
+    pub title: String,
+    pub year: Option<u16>,
+    /// `tv`, `anime` or `movie`
+    pub r#type: Option<String>,
+    pub poster: Option<String>,
+    /// SIMKL page of the item
+    #[serde(alias = "simkl_url")]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub ids: MediaIds,
+}
+
+/// The API answers a single object when `limit` is 1 and a list otherwise
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum RandomResponse {
+    Many(Vec<RandomItem>),
+    One(RandomItem),
+}
+
+impl RandomResponse {
+    pub fn into_items(self) -> Vec<RandomItem> {
+        match self {
+            RandomResponse::Many(items) => items,
+            RandomResponse::One(item) => vec![item],
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_random_request() {
+        let request = RandomRequest::new()
+            .with_service("netflix")
+            .with_genre(crate::show::TvGenre::science_fiction)
+            .with_rating(5, 10)
+            .with_years(2008, 2015)
+            .with_limit(10);
+        assert!(request.validate().is_ok());
+        assert_eq!(
+            request.build_url(),
+            "https://api.simkl.com/search/random/netflix?type=tv&genre=science-fiction&rating_from=5&rating_to=10&year_from=2008&year_to=2015&limit=10"
+        );
+        assert!(request.clone().with_limit(11).validate().is_err());
+        assert!(request.with_type(MediaType::Movie).validate().is_err());
+    }
+
+    #[test]
+    fn test_find_by_file() {
+        let request =
+            FindByFilePayload::new("Were.The.Fugawis.S01E01E02.WS.DSR.x264-NY2.mkv").with_part(2);
+        assert_eq!(request.build_url(), FIND_BY_FILE_URL);
+        assert_eq!(
+            request.body().unwrap(),
+            r#"{"file":"Were.The.Fugawis.S01E01E02.WS.DSR.x264-NY2.mkv","part":2}"#
+        );
+
+        let json = r#"{"type":"episode","episode":{"title":"Episode 1","season":1,"episode":1,"multipart":true,"ids":{"simkl":12345}},
+            "show":{"title":"We're the Fugawis","year":2005,"ids":{"simkl":123,"slug":"were-the-fugawis"}}}"#;
+        match serde_json::from_str::<FileMatch>(json).unwrap() {
+            FileMatch::Episode { episode, show } => {
+                assert_eq!(episode.season, Some(1));
+                assert_eq!(episode.multipart, Some(true));
+                assert_eq!(show.ids.simkl, Some(123));
+            }
+            _ => panic!("expected an episode"),
+        }
+    }
+
+    #[test]
+    fn test_search_request() {
+        let client_id = String::from("azerty123456");
+        assert_eq!(
+            get_search_request(
+                Some(String::from("movie")),
+                None,
+                client_id.clone(),
+                String::from("john wick 2014")
+            )
+            .unwrap(),
+            "https://api.simkl.com/search/movie?client_id=azerty123456&q=john+wick+2014"
+        );
+        assert!(
+            get_search_request(None, None, client_id.clone(), String::from("john wick")).is_err()
+        );
+        assert!(get_search_request(
+            None,
+            None,
+            client_id,
+            String::from("https://www.imdb.com/title/tt2911666/")
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_search_result() {
+        let json = r#"[
+            {"title":"John Wick","year":2014,"endpoint_type":"movies","poster":"77/773914587c4ee7e7e","ids":{"simkl_id":53536,"slug":"john-wick","tmdb":"245891"}},
+            {"title":"Your Name.","year":2016,"endpoint_type":"anime","type":"movie","ids":{"simkl_id":433548,"tmdb":"372058"}},
+            {"title":"Cowboy Bebop","year":1998,"endpoint_type":"anime","type":"tv","ids":{"simkl_id":37089,"tmdb":30991}}
+        ]"#;
+        let results: Vec<SearchResult> = serde_json::from_str(json).unwrap();
+        assert_eq!(results[0].ids.simkl, Some(53536));
+        assert_eq!(results[0].anime_type(), None);
+        assert_eq!(
+            results[0].tmdb_url().unwrap(),
+            "https://www.themoviedb.org/movie/245891"
+        );
+        assert_eq!(results[1].anime_type(), Some(AnimeType::Movie));
+        assert_eq!(results[1].tmdb_section(), TmdbSection::Movie);
+        assert_eq!(results[2].tmdb_section(), TmdbSection::Tv);
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(
+            parse_query("john wick 2014"),
+            ParsedQuery::Text {
+                query: String::from("john wick"),
+                year: Some(2014)
+            }
+        );
+        assert_eq!(
+            parse_query("2012"),
+            ParsedQuery::Text {
+                query: String::from("2012"),
+                year: None
+            }
+        );
+        let imdb = IdLookup {
+            imdb: Some(String::from("tt2911666")),
+            ..Default::default()
+        };
+        assert_eq!(parse_query("tt2911666"), ParsedQuery::Ids(imdb.clone()));
+        assert_eq!(
+            parse_query("https://www.imdb.com/title/tt2911666/"),
+            ParsedQuery::Ids(imdb)
+        );
+        assert_eq!(
+            parse_query("themoviedb.org/tv/1399-game-of-thrones"),
+            ParsedQuery::Ids(IdLookup {
+                tmdb: Some(1399),
+                r#type: Some(String::from("show")),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_query("https://myanimelist.net/anime/5114/Fullmetal_Alchemist__Brotherhood"),
+            ParsedQuery::Ids(IdLookup {
+                mal: Some(5114),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_query("https://thetvdb.com/?tab=series&id=81189"),
+            ParsedQuery::Ids(IdLookup {
+                tvdb: Some(81189),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_query("https://letterboxd.com/film/john-wick/"),
+            ParsedQuery::Url(String::from("https://letterboxd.com/film/john-wick/"))
+        );
+    }
+
     #[test]
     fn test_id_request() {
         let payload = IdLookup {
@@ -229,8 +885,24 @@ mod tests {
         };
         let client_id = String::from("azerty123456");
         assert_eq!(
-            get_search_by_id_request(payload.clone(), client_id),
-            "https://api.simkl.com/search?client_id=azerty123456&simkl=123"
+            get_search_by_id_request(payload.clone(), client_id).unwrap(),
+            "https://api.simkl.com/search/id?client_id=azerty123456&simkl=123"
+        );
+
+        let payload = IdLookup {
+            title: Some(String::from("Law & Order")),
+            r#type: Some(String::from("show")),
+            ..Default::default()
+        };
+        assert_eq!(
+            payload.build_url(),
+            "https://api.simkl.com/search/id?type=show&title=Law+%26+Order"
         );
+        assert!(IdLookup::default().validate().is_err());
+
+        let json = r#"[{"type":"show","title":"The Walking Dead","poster":"74/74415673dcdc9cdd","year":2010,"ids":{"simkl":2090,"slug":"the-walking-dead"}}]"#;
+        let results: Vec<IdLookupResult> = serde_json::from_str(json).unwrap();
+        assert_eq!(results[0].media.ids.simkl, Some(2090));
+        assert_eq!(results[0].media.year, 2010);
     }
 }
@@ -68,6 +68,51 @@ pub enum TvGenre {
     western,
 }
 
+impl TvGenre {
+    /// Slug used by the API in URLs (e.g. genre filters)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TvGenre::action => "action",
+            TvGenre::adventure => "adventure",
+            TvGenre::animation => "animation",
+            TvGenre::awards_show => "awards-show",
+            TvGenre::children => "children",
+            TvGenre::comedy => "comedy",
+            TvGenre::crime => "crime",
+            TvGenre::documentary => "documentary",
+            TvGenre::drama => "drama",
+            TvGenre::erotica => "erotica",
+            TvGenre::family => "family",
+            TvGenre::fantasy => "fantasy",
+            TvGenre::food => "food",
+            TvGenre::game_show => "game-show",
+            TvGenre::history => "history",
+            TvGenre::home_and_garden => "home-and-garden",
+            TvGenre::horror => "horror",
+            TvGenre::indie => "indie",
+            TvGenre::korean_drama => "korean-drama",
+            TvGenre::martial_arts => "martial-arts",
+            TvGenre::mini_series => "mini-series",
+            TvGenre::musical => "musical",
+            TvGenre::mystery => "mystery",
+            TvGenre::news => "news",
+            TvGenre::podcast => "podcast",
+            TvGenre::reality => "reality",
+            TvGenre::romance => "romance",
+            TvGenre::science_fiction => "science-fiction",
+            TvGenre::soap => "soap",
+            TvGenre::special_interest => "special-interest",
+            TvGenre::sport => "sport",
+            TvGenre::suspense => "suspense",
+            TvGenre::talk_show => "talk-show",
+            TvGenre::thriller => "thriller",
+            TvGenre::travel => "travel",
+            TvGenre::war => "war",
+            TvGenre::western => "western",
+        }
+    }
+}
+
 // impl Show {
 //     pub fn new(title: String) -> Self {
 //         Self {
@@ -0,0 +1,454 @@
+//! SQLite storage of the library, enabled with the `sqlite` feature.
+//!
+//! Items are stored as JSON with a few columns extracted for the queries, and every id of an item is indexed so it
+//! can be looked up by any of them. The schema version is kept in `PRAGMA user_version` and upgraded by
+//! `SqliteStore::open`.
+//!
+//! The ids index relies on the JSON functions of SQLite, built in since 3.38.
+
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    error::{Result, SimklError},
+    library::{library_key, Library},
+    sync::{library_segment, Activities, LibraryItem},
+    MediaIds, MediaType, WatchStatus,
+};
+
+/// Schema upgrades, `MIGRATIONS[n]` brings the database from version `n` to `n + 1`
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE items (
+        media_type TEXT NOT NULL,
+        key TEXT NOT NULL,
+        simkl INTEGER,
+        status TEXT,
+        user_rating INTEGER,
+        last_watched_at TEXT,
+        watched_episodes INTEGER,
+        total_episodes INTEGER,
+        not_aired_episodes INTEGER,
+        data TEXT NOT NULL,
+        PRIMARY KEY (media_type, key)
+    );
+    CREATE INDEX items_status ON items (media_type, status);
+    CREATE TABLE activities (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        data TEXT NOT NULL
+    );
+    CREATE TABLE metadata (
+        key TEXT PRIMARY KEY,
+        data TEXT NOT NULL,
+        updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+    );",
+    "CREATE TABLE item_ids (
+        media_type TEXT NOT NULL,
+        id TEXT NOT NULL,
+        key TEXT NOT NULL,
+        PRIMARY KEY (media_type, id)
+    );
+    CREATE INDEX item_ids_key ON item_ids (media_type, key);
+    INSERT OR REPLACE INTO item_ids (media_type, id, key)
+        SELECT items.media_type, ids.key || ':' || ids.value, items.key
+        FROM items, json_each(COALESCE(
+            json_extract(items.data, '$.show.ids'),
+            json_extract(items.data, '$.movie.ids')
+        )) AS ids
+        WHERE ids.key != 'slug';",
+];
+
+/// Entries of the ids index for `ids`, the same as the ones built by the migration from the stored JSON
+fn indexed_ids(ids: &MediaIds) -> Vec<String> {
+    let numbers = [
+        ("simkl", ids.simkl),
+        ("tmdb", ids.tmdb),
+        ("mal", ids.mal),
+        ("anilist", ids.anilist),
+        ("anidb", ids.anidb),
+        ("tvdb", ids.tvdb),
+        ("kitsu", ids.kitsu),
+    ];
+    ids.imdb
+        .iter()
+        .map(|imdb| format!("imdb:{}", imdb))
+        .chain(
+            numbers
+                .into_iter()
+                .filter_map(|(name, id)| Some(format!("{}:{}", name, id?))),
+        )
+        .collect()
+}
+
+fn media_type_from_segment(segment: &str) -> Result<MediaType> {
+    match segment {
+        "shows" => Ok(MediaType::Show),
+        "anime" => Ok(MediaType::Anime),
+        "movies" => Ok(MediaType::Movie),
+        _ => Err(SimklError::ParseError(format!(
+            "unknown library section: {}",
+            segment
+        ))),
+    }
+}
+
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Open or create a database and upgrade its schema
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self> {
+        let mut store = Self { conn };
+        store.migrate()?;
+        Ok(store)
+    }
+
+    pub fn schema_version(&self) -> Result<usize> {
+        let version: i64 = self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        Ok(version as usize)
+    }
+
+    fn migrate(&mut self) -> Result<()> {
+        let version = self.schema_version()?;
+        if version > MIGRATIONS.len() {
+            return Err(SimklError::InvalidParameters(format!(
+                "database schema version {} is newer than this library ({})",
+                version,
+                MIGRATIONS.len()
+            )));
+        }
+        let tx = self.conn.transaction()?;
+        for migration in &MIGRATIONS[version..] {
+            tx.execute_batch(migration)?;
+        }
+        tx.pragma_update(None, "user_version", MIGRATIONS.len() as i64)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Add or replace an item, ignored when it has no ids
+    pub fn upsert_item(&self, media_type: MediaType, item: &LibraryItem) -> Result<()> {
+        upsert_item(&self.conn, media_type, item)
+    }
+
+    /// Remove the item matching any of `ids`
+    pub fn remove_item(&self, media_type: MediaType, ids: &MediaIds) -> Result<bool> {
+        let Some(key) = self.find_key(media_type, ids)? else {
+            return Ok(false);
+        };
+        let segment = library_segment(media_type)?;
+        let tx = self.conn.unchecked_transaction()?;
+        let deleted = tx.execute(
+            "DELETE FROM items WHERE media_type = ?1 AND key = ?2",
+            params![segment, key],
+        )?;
+        tx.execute(
+            "DELETE FROM item_ids WHERE media_type = ?1 AND key = ?2",
+            params![segment, key],
+        )?;
+        tx.commit()?;
+        Ok(deleted > 0)
+    }
+
+    /// Item matching any of `ids`
+    pub fn get_item(&self, media_type: MediaType, ids: &MediaIds) -> Result<Option<LibraryItem>> {
+        let Some(key) = self.find_key(media_type, ids)? else {
+            return Ok(None);
+        };
+        let data: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT data FROM items WHERE media_type = ?1 AND key = ?2",
+                params![library_segment(media_type)?, key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(data.map(|d| serde_json::from_str(&d)).transpose()?)
+    }
+
+    /// Key of the item matching any of `ids`
+    fn find_key(&self, media_type: MediaType, ids: &MediaIds) -> Result<Option<String>> {
+        let segment = library_segment(media_type)?;
+        for id in indexed_ids(ids) {
+            let key: Option<String> = self
+                .conn
+                .query_row(
+                    "SELECT key FROM item_ids WHERE media_type = ?1 AND id = ?2",
+                    params![segment, id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if key.is_some() {
+                return Ok(key);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Replace the stored library, items and activities, in a single transaction
+    pub fn save_library(&mut self, library: &Library) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM items", [])?;
+        tx.execute("DELETE FROM item_ids", [])?;
+        for (media_type, item) in library.iter() {
+            upsert_item(&tx, media_type, item)?;
+        }
+        match &library.activities {
+            Some(activities) => save_activities(&tx, activities)?,
+            None => {
+                tx.execute("DELETE FROM activities", [])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn load_library(&self) -> Result<Library> {
+        let mut library = Library::new();
+        let mut stmt = self.conn.prepare("SELECT media_type, data FROM items")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (segment, data) = row?;
+            let item: LibraryItem = serde_json::from_str(&data)?;
+            library.insert(media_type_from_segment(&segment)?, item);
+        }
+        library.activities = self.load_activities()?;
+        Ok(library)
+    }
+
+    /// Activities of the last synchronization, the cursor of the next one
+    pub fn save_activities(&self, activities: &Activities) -> Result<()> {
+        save_activities(&self.conn, activities)
+    }
+
+    pub fn load_activities(&self) -> Result<Option<Activities>> {
+        let data: Option<String> = self
+            .conn
+            .query_row("SELECT data FROM activities WHERE id = 1", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        Ok(data.map(|d| serde_json::from_str(&d)).transpose()?)
+    }
+
+    /// Cache any response (movie details, episodes, ...), example key: `movies/53536`
+    pub fn put_metadata<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO metadata (key, data, updated_at) VALUES (?1, ?2, CURRENT_TIMESTAMP)
+             ON CONFLICT (key) DO UPDATE SET data = excluded.data, updated_at = excluded.updated_at",
+            params![key, serde_json::to_string(value)?],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_metadata<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let data: Option<String> = self
+            .conn
+            .query_row("SELECT data FROM metadata WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        Ok(data.map(|d| serde_json::from_str(&d)).transpose()?)
+    }
+
+    pub fn items_with_status(
+        &self,
+        media_type: MediaType,
+        status: WatchStatus,
+    ) -> Result<Vec<LibraryItem>> {
+        self.query_items(
+            "SELECT data FROM items WHERE media_type = ?1 AND status = ?2",
+            params![library_segment(media_type)?, status.as_str()],
+        )
+    }
+
+    /// Shows or anime being watched with aired episodes left to watch, most recently watched first
+    pub fn watching_with_unwatched_episodes(
+        &self,
+        media_type: MediaType,
+    ) -> Result<Vec<LibraryItem>> {
+        self.query_items(
+            "SELECT data FROM items
+             WHERE media_type = ?1 AND status = 'watching'
+               AND total_episodes - IFNULL(not_aired_episodes, 0) > IFNULL(watched_episodes, 0)
+             ORDER BY last_watched_at DESC",
+            params![library_segment(media_type)?],
+        )
+    }
+
+    fn query_items(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<LibraryItem>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map(params, |row| row.get::<_, String>(0))?;
+        let mut items = Vec::new();
+        for data in rows {
+            items.push(serde_json::from_str(&data?)?);
+        }
+        Ok(items)
+    }
+}
+
+fn upsert_item(conn: &Connection, media_type: MediaType, item: &LibraryItem) -> Result<()> {
+    let ids = match item.ids() {
+        Some(ids) if *ids != MediaIds::default() => ids,
+        _ => return Ok(()),
+    };
+    let segment = library_segment(media_type)?;
+    let key = serde_json::to_string(&library_key(ids))?;
+    conn.execute(
+        "INSERT OR REPLACE INTO items (media_type, key, simkl, status, user_rating, last_watched_at,
+            watched_episodes, total_episodes, not_aired_episodes, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            segment,
+            key,
+            ids.simkl,
+            item.status.map(|s| s.as_str()),
+            item.user_rating.map(|r| r.get()),
+            item.last_watched_at.map(|d| d.to_rfc3339()),
+            item.watched_episodes_count,
+            item.total_episodes_count,
+            item.not_aired_episodes_count,
+            serde_json::to_string(item)?,
+        ],
+    )?;
+    conn.execute(
+        "DELETE FROM item_ids WHERE media_type = ?1 AND key = ?2",
+        params![segment, key],
+    )?;
+    for id in indexed_ids(ids) {
+        conn.execute(
+            "INSERT OR REPLACE INTO item_ids (media_type, id, key) VALUES (?1, ?2, ?3)",
+            params![segment, id, key],
+        )?;
+    }
+    Ok(())
+}
+
+fn save_activities(conn: &Connection, activities: &Activities) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO activities (id, data) VALUES (1, ?1)",
+        [serde_json::to_string(activities)?],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::{AllItems, LibraryMedia};
+
+    fn show(
+        ids: MediaIds,
+        status: WatchStatus,
+        watched: u32,
+        total: u32,
+        not_aired: u32,
+    ) -> LibraryItem {
+        LibraryItem {
+            status: Some(status),
+            watched_episodes_count: Some(watched),
+            total_episodes_count: Some(total),
+            not_aired_episodes_count: Some(not_aired),
+            show: Some(LibraryMedia {
+                ids,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn simkl(id: u32) -> MediaIds {
+        MediaIds::new().with_simkl(id)
+    }
+
+    #[test]
+    fn test_sqlite_store() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        assert_eq!(store.schema_version().unwrap(), MIGRATIONS.len());
+
+        let mut library = Library::new();
+        library.apply_delta(AllItems {
+            shows: vec![
+                show(simkl(1), WatchStatus::Watching, 3, 10, 0),
+                show(simkl(2), WatchStatus::Watching, 8, 10, 2),
+                show(
+                    simkl(3).with_imdb(String::from("tt0903747")),
+                    WatchStatus::Completed,
+                    10,
+                    10,
+                    0,
+                ),
+            ],
+            ..Default::default()
+        });
+        library.record_activities(Activities::default());
+        store.save_library(&library).unwrap();
+        assert_eq!(store.load_library().unwrap(), library);
+
+        let pending = store
+            .watching_with_unwatched_episodes(MediaType::Show)
+            .unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].ids().unwrap().simkl, Some(1));
+        assert_eq!(
+            store
+                .items_with_status(MediaType::Show, WatchStatus::Completed)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let imdb = MediaIds::new().with_imdb(String::from("tt0903747"));
+        assert!(store.get_item(MediaType::Show, &imdb).unwrap().is_some());
+        assert!(store.get_item(MediaType::Movie, &imdb).unwrap().is_none());
+        assert!(store.remove_item(MediaType::Show, &imdb).unwrap());
+        assert!(store
+            .get_item(MediaType::Show, &simkl(3))
+            .unwrap()
+            .is_none());
+        assert!(!store.remove_item(MediaType::Show, &imdb).unwrap());
+
+        store.put_metadata("tv/1", &vec![1, 2]).unwrap();
+        assert_eq!(
+            store.get_metadata::<Vec<u32>>("tv/1").unwrap(),
+            Some(vec![1, 2])
+        );
+    }
+
+    #[test]
+    fn test_ids_index_migration() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        let ids = simkl(1).with_imdb(String::from("tt0903747"));
+        // row written by a version without the ids index
+        conn.execute(
+            "INSERT INTO items (media_type, key, simkl, data) VALUES ('shows', ?1, 1, ?2)",
+            params![
+                serde_json::to_string(&library_key(&ids)).unwrap(),
+                serde_json::to_string(&show(ids, WatchStatus::Watching, 3, 10, 0)).unwrap()
+            ],
+        )
+        .unwrap();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+
+        let store = SqliteStore::with_connection(conn).unwrap();
+        assert_eq!(store.schema_version().unwrap(), MIGRATIONS.len());
+        let imdb = MediaIds::new().with_imdb(String::from("tt0903747"));
+        let item = store.get_item(MediaType::Show, &imdb).unwrap().unwrap();
+        assert_eq!(item.ids().unwrap().simkl, Some(1));
+    }
+}
@@ -1,11 +1,19 @@
-use crate::{Extended, API_URL};
-use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use crate::{
+    anime::AnimeType,
+    error::{Result, SimklError},
+    get_extended_parameter,
+    request::SimklRequest,
+    search::lenient_anime_type,
+    Extended, MediaIds, MediaType, WatchStatus,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
 
 /// to retrieve the latest activity timestamps for the user. This endpoint provides timestamps for various categories
 /// and media types, indicating the last time each was updated.
 ///
-/// ```
+/// ```text
 /// POST https://api.simkl.com/sync/activities
 // Headers:
 ///     Authorization: Bearer [token]
@@ -13,12 +21,12 @@ use serde::Deserialize;
 /// ```
 pub const USER_ACTIVITIES: &str = "https://api.simkl.com/sync/activities";
 
-#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SyncSettings {
     pub all: DateTime<Utc>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MediaActivity {
     pub all: DateTime<Utc>,
     pub rated_at: DateTime<Utc>,
@@ -31,7 +39,21 @@ pub struct MediaActivity {
     pub removed_from_list: DateTime<Utc>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+impl MediaActivity {
+    /// Last change of a list, `None` for lists without timestamp (movies are never watching nor on hold)
+    pub fn status(&self, status: WatchStatus) -> Option<DateTime<Utc>> {
+        match status {
+            WatchStatus::Watching => self.watching,
+            WatchStatus::PlanToWatch => Some(self.plan_to_watch),
+            WatchStatus::Hold => self.hold,
+            WatchStatus::Completed => Some(self.completed),
+            WatchStatus::Dropped => Some(self.dropped),
+            WatchStatus::NotInteresting => None,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Activities {
     pub all: DateTime<Utc>,
     pub settings: SyncSettings,
@@ -40,74 +62,1444 @@ pub struct Activities {
     pub movies: MediaActivity,
 }
 
+impl Activities {
+    pub fn media(&self, media_type: MediaType) -> Option<&MediaActivity> {
+        match media_type {
+            MediaType::Show => Some(&self.tv_shows),
+            MediaType::Anime => Some(&self.anime),
+            MediaType::Movie => Some(&self.movies),
+            MediaType::Episode => None,
+        }
+    }
+}
+
 /// Retrieve the entire watchlist.
 ///
-/// ```
-/// POST https://api.simkl.com/sync/all-items/
+/// ```text
+/// GET https://api.simkl.com/sync/all-items/
 // Headers:
 ///     Authorization: Bearer [token]
 ///     simkl-api-key: [client_id]
 /// ```
 pub const USER_ITEMS: &str = "https://api.simkl.com/sync/all-items/";
 
-/// Instead of getting everything, you can get only one element (animes, movies, shows, ..., ratings, ...). You can
-/// also use a starting date
+/// Section of the library for a media type
+pub(crate) fn library_segment(media_type: MediaType) -> Result<&'static str> {
+    match media_type {
+        MediaType::Movie => Ok("movies"),
+        MediaType::Show => Ok("shows"),
+        MediaType::Anime => Ok("anime"),
+        MediaType::Episode => Err(SimklError::InvalidParameters(
+            "episodes are not a library section".to_string(),
+        )),
+    }
+}
+
+/// Section of a sync payload for a media type, episodes go with the shows
+pub(crate) fn section<'a, T>(
+    media_type: MediaType,
+    movies: &'a mut Vec<T>,
+    shows: &'a mut Vec<T>,
+    anime: &'a mut Vec<T>,
+) -> &'a mut Vec<T> {
+    match media_type {
+        MediaType::Movie => movies,
+        MediaType::Anime => anime,
+        _ => shows,
+    }
+}
+
+/// Items of the three sections of a payload with their media type
+pub(crate) fn sections<'a, T>(
+    movies: &'a [T],
+    shows: &'a [T],
+    anime: &'a [T],
+) -> impl Iterator<Item = (MediaType, &'a T)> {
+    let movies = movies.iter().map(|i| (MediaType::Movie, i));
+    let shows = shows.iter().map(|i| (MediaType::Show, i));
+    let anime = anime.iter().map(|i| (MediaType::Anime, i));
+    movies.chain(shows).chain(anime)
+}
+
+/// Get the user library, the response is an `AllItems` (or `null` when nothing changed since `date_from`).
+///
+/// Examples:
+/// * `GET https://api.simkl.com/sync/all-items/`
+/// * `GET https://api.simkl.com/sync/all-items/shows/watching?date_from=2024-03-01T20:00:00Z&episode_watched_at=yes`
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct AllItemsRequest {
+    /// Every section when empty
+    pub media_type: Option<MediaType>,
+    /// Every list when empty, requires `media_type`
+    pub status: Option<WatchStatus>,
+    /// Only items changed since this date, use the timestamps of `Activities`
+    pub date_from: Option<DateTime<Utc>>,
+    /// Example: `full`
+    pub extended: Option<String>,
+    /// Add the watch date of every episode
+    pub episode_watched_at: bool,
+    /// Add the user notes
+    pub memos: bool,
+}
+
+impl AllItemsRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_type(mut self, media_type: MediaType) -> Self {
+        self.media_type = Some(media_type);
+        self
+    }
+
+    pub fn with_status(mut self, status: WatchStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn with_date_from(mut self, date_from: DateTime<Utc>) -> Self {
+        self.date_from = Some(date_from);
+        self
+    }
+
+    pub fn with_extended(mut self, extended: impl Into<String>) -> Self {
+        self.extended = Some(extended.into());
+        self
+    }
+
+    pub fn with_episode_watched_at(mut self) -> Self {
+        self.episode_watched_at = true;
+        self
+    }
+
+    pub fn with_memos(mut self) -> Self {
+        self.memos = true;
+        self
+    }
+}
+
+impl SimklRequest for AllItemsRequest {
+    fn endpoint(&self) -> String {
+        let mut result = String::from("/sync/all-items/");
+        if let Some(segment) = self.media_type.and_then(|t| library_segment(t).ok()) {
+            result.push_str(segment);
+            result.push('/');
+            if let Some(status) = self.status {
+                result.push_str(status.as_str());
+            }
+        }
+        result
+    }
+
+    fn query_params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        if let Some(date) = self.date_from {
+            params.push((
+                "date_from".to_string(),
+                date.to_rfc3339_opts(SecondsFormat::Secs, true),
+            ));
+        }
+        if let Some(ref extended) = self.extended {
+            params.push(("extended".to_string(), extended.clone()));
+        }
+        if self.episode_watched_at {
+            params.push(("episode_watched_at".to_string(), "yes".to_string()));
+        }
+        if self.memos {
+            params.push(("memos".to_string(), "yes".to_string()));
+        }
+        params
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(media_type) = self.media_type {
+            library_segment(media_type)?;
+        }
+        match (self.media_type, self.status) {
+            (None, Some(_)) => Err(SimklError::InvalidParameters(
+                "a status requires a media type".to_string(),
+            )),
+            (Some(media_type), Some(status)) if !status.applies_to(media_type) => Err(
+                SimklError::InvalidParameters(format!("a {} cannot be {}", media_type, status)),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Instead of getting everything, you can get only one element (animes, movies, shows, ..., ratings, ...), optionally
+/// restricted to one list. You can also use a starting date. See `AllItemsRequest` for the other filters.
 pub fn get_all_items_request(
     what: Option<String>,
+    status: Option<WatchStatus>,
     from: Option<DateTime<Utc>>,
-    _extended: Option<Extended>,
-) -> String {
-    // TODO: may use payload instead because we can filter on more stuffs: https://simkl.docs.apiary.io/reference/sync/get-all-items
+    extended: Option<Extended>,
+) -> Result<String> {
     let mut result = String::from(USER_ITEMS);
     if let Some(w) = what {
         result.push_str(&w);
         result.push('/');
+        if let Some(s) = status {
+            result.push_str(s.as_str());
+        }
     }
+    let mut url = Url::parse(&result)?;
     if let Some(d) = from {
-        result.push_str("?date_from=");
-        result.push_str(&d.to_rfc3339());
+        url.query_pairs_mut()
+            .append_pair("date_from", &d.to_rfc3339_opts(SecondsFormat::Secs, true));
+    }
+    if let Some(ext) = extended {
+        let ext = get_extended_parameter(ext)
+            .map_err(|e| SimklError::InvalidParameters(e.to_string()))?;
+        url.query_pairs_mut().append_pair("extended", &ext);
+    }
+    Ok(url.into())
+}
+
+/// Movie, show or anime of the library
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryMedia {
+    #[serde(default)]
+    pub title: String,
+    pub poster: Option<String>,
+    pub year: Option<u16>,
+    pub runtime: Option<u32>,
+    #[serde(default)]
+    pub ids: MediaIds,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchedEpisode {
+    pub number: u16,
+    /// With `episode_watched_at` only
+    pub watched_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchedSeason {
+    pub number: u16,
+    #[serde(default)]
+    pub episodes: Vec<WatchedEpisode>,
+}
+
+/// User note, with `memos` only
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Memo {
+    pub text: Option<String>,
+    #[serde(default)]
+    pub is_private: bool,
+}
+
+/// Entry of the user library
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryItem {
+    pub status: Option<WatchStatus>,
+    pub added_to_watchlist_at: Option<DateTime<Utc>>,
+    pub last_watched_at: Option<DateTime<Utc>>,
+    pub user_rated_at: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "lenient_rating")]
+    pub user_rating: Option<UserRating>,
+    /// Last watched episode, example: `S01E02`
+    pub last_watched: Option<String>,
+    /// Next episode to watch, example: `S01E03`
+    pub next_to_watch: Option<String>,
+    pub watched_episodes_count: Option<u32>,
+    pub total_episodes_count: Option<u32>,
+    pub not_aired_episodes_count: Option<u32>,
+    #[serde(default, deserialize_with = "lenient_anime_type")]
+    pub anime_type: Option<AnimeType>,
+    pub memo: Option<Memo>,
+    /// Set for shows and anime
+    pub show: Option<LibraryMedia>,
+    /// Set for movies
+    pub movie: Option<LibraryMedia>,
+    #[serde(default)]
+    pub seasons: Vec<WatchedSeason>,
+}
+
+/// Parse an episode code such as `S01E03`, the season is missing for anime (`E12`)
+pub fn parse_episode_code(code: &str) -> Option<(Option<u16>, u16)> {
+    let code = code.trim().to_uppercase();
+    let (season, episode) = match code.split_once('E') {
+        Some((season, episode)) => (season, episode),
+        None => return None,
+    };
+    let season = match season.strip_prefix('S') {
+        Some(s) => Some(s.parse().ok()?),
+        None if season.is_empty() => None,
+        None => return None,
+    };
+    Some((season, episode.parse().ok()?))
+}
+
+impl LibraryItem {
+    pub fn media(&self) -> Option<&LibraryMedia> {
+        self.show.as_ref().or(self.movie.as_ref())
+    }
+
+    pub fn ids(&self) -> Option<&MediaIds> {
+        self.media().map(|m| &m.ids)
+    }
+
+    /// Season and episode of `next_to_watch`
+    pub fn next_episode(&self) -> Option<(Option<u16>, u16)> {
+        self.next_to_watch.as_deref().and_then(parse_episode_code)
+    }
+
+    pub fn is_episode_watched(&self, season: u16, episode: u16) -> bool {
+        self.seasons
+            .iter()
+            .filter(|s| s.number == season)
+            .any(|s| s.episodes.iter().any(|e| e.number == episode))
+    }
+}
+
+/// Response of `AllItemsRequest`, sections that were not requested are empty
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AllItems {
+    #[serde(default)]
+    pub shows: Vec<LibraryItem>,
+    #[serde(default)]
+    pub anime: Vec<LibraryItem>,
+    #[serde(default)]
+    pub movies: Vec<LibraryItem>,
+}
+
+impl AllItems {
+    /// Parse a response body, the API answers `null` when nothing changed since `date_from`
+    pub fn from_body(body: &str) -> Result<Self> {
+        let items: Option<AllItems> = serde_json::from_str(body)?;
+        Ok(items.unwrap_or_default())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shows.is_empty() && self.anime.is_empty() && self.movies.is_empty()
+    }
+}
+
+/// Episode number inside a season, `watched_at` is only used when adding to the history
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncEpisode {
+    pub number: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watched_at: Option<DateTime<Utc>>,
+}
+
+/// Season of a show, the whole season is targeted when `episodes` is empty
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncSeason {
+    pub number: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watched_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub episodes: Vec<SyncEpisode>,
+}
+
+/// Movie, show or anime sent to the sync endpoints. Without `seasons` nor `episodes` the whole item is targeted.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncItem {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<u16>,
+    #[serde(default)]
+    pub ids: MediaIds,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watched_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub seasons: Vec<SyncSeason>,
+    /// Episodes of anime without seasons
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub episodes: Vec<SyncEpisode>,
+}
+
+impl SyncItem {
+    pub fn new(ids: MediaIds) -> Self {
+        Self {
+            ids,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_year(mut self, year: u16) -> Self {
+        self.year = Some(year);
+        self
+    }
+
+    pub fn with_watched_at(mut self, watched_at: DateTime<Utc>) -> Self {
+        self.watched_at = Some(watched_at);
+        self
+    }
+
+    fn season_mut(&mut self, number: u16) -> &mut SyncSeason {
+        match self.seasons.iter().position(|s| s.number == number) {
+            Some(index) => &mut self.seasons[index],
+            None => {
+                self.seasons.push(SyncSeason {
+                    number,
+                    ..Default::default()
+                });
+                self.seasons.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Target a whole season
+    pub fn with_season(mut self, number: u16) -> Self {
+        self.season_mut(number);
+        self
+    }
+
+    /// Target some episodes of a season
+    pub fn with_episodes(mut self, season: u16, episodes: impl IntoIterator<Item = u16>) -> Self {
+        let season = self.season_mut(season);
+        for number in episodes {
+            if !season.episodes.iter().any(|e| e.number == number) {
+                season.episodes.push(SyncEpisode {
+                    number,
+                    watched_at: None,
+                });
+            }
+        }
+        self
+    }
+
+    /// Target an episode of a season with its own date
+    pub fn with_episode(mut self, season: u16, episode: SyncEpisode) -> Self {
+        let season = self.season_mut(season);
+        season.episodes.retain(|e| e.number != episode.number);
+        season.episodes.push(episode);
+        self
+    }
+
+    /// Target episodes of an anime without seasons
+    pub fn with_absolute_episodes(mut self, episodes: impl IntoIterator<Item = u16>) -> Self {
+        for number in episodes {
+            if !self.episodes.iter().any(|e| e.number == number) {
+                self.episodes.push(SyncEpisode {
+                    number,
+                    watched_at: None,
+                });
+            }
+        }
+        self
+    }
+
+    /// Same item without any `watched_at`, for the endpoints that do not use dates
+    pub fn without_dates(mut self) -> Self {
+        self.watched_at = None;
+        for season in self.seasons.iter_mut() {
+            season.watched_at = None;
+            season.episodes.iter_mut().for_each(|e| e.watched_at = None);
+        }
+        self.episodes.iter_mut().for_each(|e| e.watched_at = None);
+        self
+    }
+
+    /// Target the whole movie, show or anime
+    pub fn is_whole(&self) -> bool {
+        self.seasons.is_empty() && self.episodes.is_empty()
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !self.ids.has_any_id() && self.title.is_none() {
+            return Err(SimklError::InvalidParameters(
+                "sync items need an id or a title".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Episode identified by its own ids
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncEpisodeItem {
+    #[serde(default)]
+    pub ids: MediaIds,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watched_at: Option<DateTime<Utc>>,
+}
+
+fn validate_items(groups: [&Vec<SyncItem>; 3], episodes: &[SyncEpisodeItem]) -> Result<()> {
+    if groups.iter().all(|g| g.is_empty()) && episodes.is_empty() {
+        return Err(SimklError::InvalidParameters(
+            "payload is empty".to_string(),
+        ));
+    }
+    for item in groups.into_iter().flatten() {
+        item.validate()?;
+    }
+    if episodes.iter().any(|e| !e.ids.has_any_id()) {
+        return Err(SimklError::InvalidParameters(
+            "episodes need an id".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Add items to the watched history, the response is a `HistoryResponse`.
+///
+/// ```text
+/// POST https://api.simkl.com/sync/history
+/// ```
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryPayload {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub movies: Vec<SyncItem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shows: Vec<SyncItem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anime: Vec<SyncItem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub episodes: Vec<SyncEpisodeItem>,
+}
+
+impl HistoryPayload {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_movie(mut self, item: SyncItem) -> Self {
+        self.movies.push(item);
+        self
+    }
+
+    pub fn add_show(mut self, item: SyncItem) -> Self {
+        self.shows.push(item);
+        self
+    }
+
+    pub fn add_anime(mut self, item: SyncItem) -> Self {
+        self.anime.push(item);
+        self
+    }
+
+    pub fn add_episode(mut self, ids: MediaIds, watched_at: Option<DateTime<Utc>>) -> Self {
+        self.episodes.push(SyncEpisodeItem { ids, watched_at });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.movies.is_empty()
+            && self.shows.is_empty()
+            && self.anime.is_empty()
+            && self.episodes.is_empty()
+    }
+}
+
+impl SimklRequest for HistoryPayload {
+    fn endpoint(&self) -> String {
+        "/sync/history".to_string()
+    }
+
+    fn method(&self) -> &'static str {
+        "POST"
+    }
+
+    fn body(&self) -> Option<String> {
+        serde_json::to_string(self).ok()
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        vec![("Content-Type".to_string(), "application/json".to_string())]
+    }
+
+    fn validate(&self) -> Result<()> {
+        validate_items([&self.movies, &self.shows, &self.anime], &self.episodes)
+    }
+}
+
+/// Number of items handled by a sync request
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncCounts {
+    #[serde(default)]
+    pub movies: u32,
+    #[serde(default)]
+    pub shows: u32,
+    #[serde(default)]
+    pub anime: u32,
+    #[serde(default)]
+    pub episodes: u32,
+}
+
+impl SyncCounts {
+    pub fn total(&self) -> u32 {
+        self.movies + self.shows + self.anime + self.episodes
+    }
+
+    pub fn merge(&mut self, other: SyncCounts) {
+        self.movies += other.movies;
+        self.shows += other.shows;
+        self.anime += other.anime;
+        self.episodes += other.episodes;
     }
-    result
 }
 
-pub fn get_add_to_history_request() -> String {
-    let mut result = String::from(API_URL);
-    result.push_str("sync/history");
-    // TODO: https://simkl.docs.apiary.io/#reference/sync/add-items-to-the-history/add-items-to-watched/watching-history?console=1
-    result
+/// Items sent back by the API because they could not be matched
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotFound {
+    #[serde(default)]
+    pub movies: Vec<SyncItem>,
+    #[serde(default)]
+    pub shows: Vec<SyncItem>,
+    #[serde(default)]
+    pub anime: Vec<SyncItem>,
+    #[serde(default)]
+    pub episodes: Vec<SyncEpisodeItem>,
 }
 
-pub fn get_remove_from_history_request() -> String {
-    let mut result = String::from(API_URL);
-    result.push_str("sync/history/remove");
-    // TODO: https://simkl.docs.apiary.io/#reference/sync/remove-items-from-history-and-from-lists/remove-items-from-watched/watching-history?console=1
-    result
+impl NotFound {
+    pub fn len(&self) -> usize {
+        self.movies.len() + self.shows.len() + self.anime.len() + self.episodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn merge(&mut self, other: NotFound) {
+        self.movies.extend(other.movies);
+        self.shows.extend(other.shows);
+        self.anime.extend(other.anime);
+        self.episodes.extend(other.episodes);
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryResponse {
+    #[serde(default)]
+    pub added: SyncCounts,
+    #[serde(default)]
+    pub not_found: NotFound,
+}
+
+/// Remove items from the watched history, the response is a `RemoveHistoryResponse`.
+///
+/// Whole titles, seasons or episodes can be targeted the same way as with `HistoryPayload`, dates are dropped.
+///
+/// ```text
+/// POST https://api.simkl.com/sync/history/remove
+/// ```
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoveHistoryPayload {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub movies: Vec<SyncItem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shows: Vec<SyncItem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anime: Vec<SyncItem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub episodes: Vec<SyncEpisodeItem>,
 }
 
-pub fn get_add_ratings_request() -> String {
-    let mut result = String::from(API_URL);
-    result.push_str("sync/ratings");
-    // TODO: https://simkl.docs.apiary.io/#reference/sync/add-ratings/add-new-ratings?console=1
-    result
+impl RemoveHistoryPayload {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_movie(mut self, item: SyncItem) -> Self {
+        self.movies.push(item.without_dates());
+        self
+    }
+
+    pub fn add_show(mut self, item: SyncItem) -> Self {
+        self.shows.push(item.without_dates());
+        self
+    }
+
+    pub fn add_anime(mut self, item: SyncItem) -> Self {
+        self.anime.push(item.without_dates());
+        self
+    }
+
+    pub fn add_episode(mut self, ids: MediaIds) -> Self {
+        self.episodes.push(SyncEpisodeItem {
+            ids,
+            watched_at: None,
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.movies.is_empty()
+            && self.shows.is_empty()
+            && self.anime.is_empty()
+            && self.episodes.is_empty()
+    }
 }
 
-pub fn get_remove_ratings_request() -> String {
-    let mut result = String::from(API_URL);
-    result.push_str("sync/ratings/remove");
-    // TODO: https://simkl.docs.apiary.io/#reference/sync/remove-ratings/remove-ratings?console=1
-    result
+impl SimklRequest for RemoveHistoryPayload {
+    fn endpoint(&self) -> String {
+        "/sync/history/remove".to_string()
+    }
+
+    fn method(&self) -> &'static str {
+        "POST"
+    }
+
+    fn body(&self) -> Option<String> {
+        serde_json::to_string(self).ok()
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        vec![("Content-Type".to_string(), "application/json".to_string())]
+    }
+
+    fn validate(&self) -> Result<()> {
+        validate_items([&self.movies, &self.shows, &self.anime], &self.episodes)
+    }
 }
 
-pub fn get_add_to_list_request() -> String {
-    let mut result = String::from(API_URL);
-    result.push_str("sync/add-to-list");
-    // TODO: https://simkl.docs.apiary.io/#reference/sync/add-item-to-the-list/add-items-to-specific-list?console=1
-    result
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoveHistoryResponse {
+    #[serde(default)]
+    pub deleted: SyncCounts,
+    #[serde(default)]
+    pub not_found: NotFound,
 }
 
-pub fn get_check_if_watched_request() -> String {
-    let mut result = String::from(API_URL);
-    result.push_str("sync/watched");
-    // TODO: https://simkl.docs.apiary.io/#reference/sync/check-if-watched/get-specific-user's-watched-items?console=1
-    result
+/// Rating given by the user, from 1 to 10
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub struct UserRating(u8);
+
+impl UserRating {
+    pub fn new(value: u8) -> Result<Self> {
+        if (1..=10).contains(&value) {
+            Ok(Self(value))
+        } else {
+            Err(SimklError::InvalidParameters(format!(
+                "rating must be between 1 and 10, got {}",
+                value
+            )))
+        }
+    }
+
+    pub fn get(&self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for UserRating {
+    type Error = SimklError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        Self::new(value)
+    }
+}
+
+impl From<UserRating> for u8 {
+    fn from(rating: UserRating) -> Self {
+        rating.0
+    }
+}
+
+/// A rating out of range (or sent as a string) is dropped instead of failing the whole library
+fn lenient_rating<'de, D>(deserializer: D) -> std::result::Result<Option<UserRating>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Option<serde_json::Value> = Option::deserialize(deserializer)?;
+    let rating = match value {
+        Some(serde_json::Value::Number(n)) => n.as_u64(),
+        Some(serde_json::Value::String(s)) => s.trim().parse().ok(),
+        _ => None,
+    };
+    Ok(rating
+        .and_then(|r| u8::try_from(r).ok())
+        .and_then(|r| UserRating::new(r).ok()))
+}
+
+/// Movie, show or anime with the rating to give
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RatedItem {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<u16>,
+    #[serde(default)]
+    pub ids: MediaIds,
+    pub rating: UserRating,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rated_at: Option<DateTime<Utc>>,
+}
+
+impl RatedItem {
+    pub fn new(ids: MediaIds, rating: UserRating) -> Self {
+        Self {
+            title: None,
+            year: None,
+            ids,
+            rating,
+            rated_at: None,
+        }
+    }
+
+    pub fn with_rated_at(mut self, rated_at: DateTime<Utc>) -> Self {
+        self.rated_at = Some(rated_at);
+        self
+    }
+}
+
+/// Rate items, the response is a `RatingsResponse`.
+///
+/// ```text
+/// POST https://api.simkl.com/sync/ratings
+/// ```
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RatingsPayload {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub movies: Vec<RatedItem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shows: Vec<RatedItem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anime: Vec<RatedItem>,
+}
+
+impl RatingsPayload {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_movie(mut self, item: RatedItem) -> Self {
+        self.movies.push(item);
+        self
+    }
+
+    pub fn add_show(mut self, item: RatedItem) -> Self {
+        self.shows.push(item);
+        self
+    }
+
+    pub fn add_anime(mut self, item: RatedItem) -> Self {
+        self.anime.push(item);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.movies.is_empty() && self.shows.is_empty() && self.anime.is_empty()
+    }
+}
+
+impl SimklRequest for RatingsPayload {
+    fn endpoint(&self) -> String {
+        "/sync/ratings".to_string()
+    }
+
+    fn method(&self) -> &'static str {
+        "POST"
+    }
+
+    fn body(&self) -> Option<String> {
+        serde_json::to_string(self).ok()
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        vec![("Content-Type".to_string(), "application/json".to_string())]
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.is_empty() {
+            return Err(SimklError::InvalidParameters(
+                "payload is empty".to_string(),
+            ));
+        }
+        let items = self.movies.iter().chain(&self.shows).chain(&self.anime);
+        for item in items {
+            if !item.ids.has_any_id() && item.title.is_none() {
+                return Err(SimklError::InvalidParameters(
+                    "rated items need an id or a title".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RatingsResponse {
+    #[serde(default)]
+    pub added: SyncCounts,
+    #[serde(default)]
+    pub not_found: NotFound,
+}
+
+/// Remove ratings, the response is a `RemoveRatingsResponse`.
+///
+/// ```text
+/// POST https://api.simkl.com/sync/ratings/remove
+/// ```
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoveRatingsPayload {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub movies: Vec<SyncItem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shows: Vec<SyncItem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anime: Vec<SyncItem>,
+}
+
+impl RemoveRatingsPayload {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_movie(mut self, ids: MediaIds) -> Self {
+        self.movies.push(SyncItem::new(ids));
+        self
+    }
+
+    pub fn add_show(mut self, ids: MediaIds) -> Self {
+        self.shows.push(SyncItem::new(ids));
+        self
+    }
+
+    pub fn add_anime(mut self, ids: MediaIds) -> Self {
+        self.anime.push(SyncItem::new(ids));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.movies.is_empty() && self.shows.is_empty() && self.anime.is_empty()
+    }
+}
+
+impl SimklRequest for RemoveRatingsPayload {
+    fn endpoint(&self) -> String {
+        "/sync/ratings/remove".to_string()
+    }
+
+    fn method(&self) -> &'static str {
+        "POST"
+    }
+
+    fn body(&self) -> Option<String> {
+        serde_json::to_string(self).ok()
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        vec![("Content-Type".to_string(), "application/json".to_string())]
+    }
+
+    fn validate(&self) -> Result<()> {
+        validate_items([&self.movies, &self.shows, &self.anime], &[])
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoveRatingsResponse {
+    #[serde(default)]
+    pub deleted: SyncCounts,
+    #[serde(default)]
+    pub not_found: NotFound,
+}
+
+/// Movie, show or anime with the list to move it to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListItem {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<u16>,
+    #[serde(default)]
+    pub ids: MediaIds,
+    pub to: WatchStatus,
+    /// Completion date when moved to `completed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watched_at: Option<DateTime<Utc>>,
+}
+
+impl ListItem {
+    pub fn new(ids: MediaIds, to: WatchStatus) -> Self {
+        Self {
+            title: None,
+            year: None,
+            ids,
+            to,
+            watched_at: None,
+        }
+    }
+
+    pub fn with_watched_at(mut self, watched_at: DateTime<Utc>) -> Self {
+        self.watched_at = Some(watched_at);
+        self
+    }
+}
+
+/// Add items to a list or move them from one list to another, the response is an `AddToListResponse`.
+///
+/// ```text
+/// POST https://api.simkl.com/sync/add-to-list
+/// ```
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddToListPayload {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub movies: Vec<ListItem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shows: Vec<ListItem>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anime: Vec<ListItem>,
+}
+
+impl AddToListPayload {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_movie(mut self, item: ListItem) -> Self {
+        self.movies.push(item);
+        self
+    }
+
+    pub fn add_show(mut self, item: ListItem) -> Self {
+        self.shows.push(item);
+        self
+    }
+
+    pub fn add_anime(mut self, item: ListItem) -> Self {
+        self.anime.push(item);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.movies.is_empty() && self.shows.is_empty() && self.anime.is_empty()
+    }
+}
+
+impl SimklRequest for AddToListPayload {
+    fn endpoint(&self) -> String {
+        "/sync/add-to-list".to_string()
+    }
+
+    fn method(&self) -> &'static str {
+        "POST"
+    }
+
+    fn body(&self) -> Option<String> {
+        serde_json::to_string(self).ok()
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        vec![("Content-Type".to_string(), "application/json".to_string())]
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.is_empty() {
+            return Err(SimklError::InvalidParameters(
+                "payload is empty".to_string(),
+            ));
+        }
+        let groups = [
+            (MediaType::Movie, &self.movies),
+            (MediaType::Show, &self.shows),
+            (MediaType::Anime, &self.anime),
+        ];
+        for (media_type, items) in groups {
+            for item in items {
+                if !item.ids.has_any_id() && item.title.is_none() {
+                    return Err(SimklError::InvalidParameters(
+                        "list items need an id or a title".to_string(),
+                    ));
+                }
+                if !item.to.applies_to(media_type) {
+                    return Err(SimklError::InvalidParameters(format!(
+                        "a {} cannot be moved to {}",
+                        media_type, item.to
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Items moved by `AddToListPayload`
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListChanges {
+    #[serde(default)]
+    pub movies: Vec<ListItem>,
+    #[serde(default)]
+    pub shows: Vec<ListItem>,
+    #[serde(default)]
+    pub anime: Vec<ListItem>,
+}
+
+impl ListChanges {
+    pub fn merge(&mut self, other: ListChanges) {
+        self.movies.extend(other.movies);
+        self.shows.extend(other.shows);
+        self.anime.extend(other.anime);
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddToListResponse {
+    #[serde(default)]
+    pub added: ListChanges,
+    #[serde(default)]
+    pub not_found: NotFound,
+}
+
+/// Item to check, an episode can be targeted with `season` and `episode` (only `episode` for anime)
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchedCheckItem {
+    #[serde(flatten)]
+    pub ids: MediaIds,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<MediaType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub season: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub episode: Option<u16>,
+}
+
+impl WatchedCheckItem {
+    pub fn new(ids: MediaIds) -> Self {
+        Self {
+            ids,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_type(mut self, media_type: MediaType) -> Self {
+        self.r#type = Some(media_type);
+        self
+    }
+
+    pub fn with_episode(mut self, season: Option<u16>, episode: u16) -> Self {
+        self.season = season;
+        self.episode = Some(episode);
+        self
+    }
+}
+
+/// Check whether items are in the user history without downloading the whole library, the response is a list of
+/// `WatchedCheckResult` in the same order.
+///
+/// ```text
+/// POST https://api.simkl.com/sync/watched
+/// ```
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct WatchedCheckRequest {
+    pub items: Vec<WatchedCheckItem>,
+    /// Also return watched and total episodes counts
+    pub counters: bool,
+}
+
+impl WatchedCheckRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_item(mut self, item: WatchedCheckItem) -> Self {
+        self.items.push(item);
+        self
+    }
+
+    pub fn with_counters(mut self) -> Self {
+        self.counters = true;
+        self
+    }
+}
+
+impl SimklRequest for WatchedCheckRequest {
+    fn endpoint(&self) -> String {
+        "/sync/watched".to_string()
+    }
+
+    fn method(&self) -> &'static str {
+        "POST"
+    }
+
+    fn query_params(&self) -> Vec<(String, String)> {
+        if self.counters {
+            vec![("extended".to_string(), "counters".to_string())]
+        } else {
+            vec![]
+        }
+    }
+
+    fn body(&self) -> Option<String> {
+        serde_json::to_string(&self.items).ok()
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        vec![("Content-Type".to_string(), "application/json".to_string())]
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.items.is_empty() {
+            return Err(SimklError::InvalidParameters(
+                "no item to check".to_string(),
+            ));
+        }
+        for item in &self.items {
+            if !item.ids.has_any_id() {
+                return Err(SimklError::InvalidParameters(
+                    "items to check need an id".to_string(),
+                ));
+            }
+            if item.season.is_some() && item.episode.is_none() {
+                return Err(SimklError::InvalidParameters(
+                    "season is given without an episode".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The API answers `true`, `false` or `"not_found"`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WatchedResult {
+    Watched,
+    NotWatched,
+    NotFound,
+}
+
+impl<'de> Deserialize<'de> for WatchedResult {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bool(bool),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Bool(true) => Ok(WatchedResult::Watched),
+            Raw::Bool(false) => Ok(WatchedResult::NotWatched),
+            Raw::Text(t) if t == "not_found" => Ok(WatchedResult::NotFound),
+            Raw::Text(t) => Err(serde::de::Error::custom(format!(
+                "unexpected result: {}",
+                t
+            ))),
+        }
+    }
+}
+
+/// Status of a checked item, the checked item is sent back with it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WatchedCheckResult {
+    #[serde(flatten)]
+    pub item: WatchedCheckItem,
+    pub result: WatchedResult,
+    /// List the item is in
+    pub list: Option<WatchStatus>,
+    pub last_watched_at: Option<DateTime<Utc>>,
+    /// With counters only
+    pub episodes_watched: Option<u32>,
+    /// With counters only
+    #[serde(alias = "episodes_total")]
+    pub total_episodes: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_history_payload() {
+        let watched_at = Utc.with_ymd_and_hms(2024, 3, 1, 20, 0, 0).unwrap();
+        let payload = HistoryPayload::new()
+            .add_movie(
+                SyncItem::new(MediaIds::new().with_imdb(String::from("tt2911666")))
+                    .with_watched_at(watched_at),
+            )
+            .add_show(
+                SyncItem::new(MediaIds::new().with_simkl(17465))
+                    .with_season(1)
+                    .with_episodes(2, [1, 2]),
+            );
+        assert!(payload.validate().is_ok());
+        assert_eq!(payload.build_url(), "https://api.simkl.com/sync/history");
+        assert_eq!(
+            payload.body().unwrap(),
+            r#"{"movies":[{"ids":{"imdb":"tt2911666"},"watched_at":"2024-03-01T20:00:00Z"}],"shows":[{"ids":{"simkl":17465},"seasons":[{"number":1},{"number":2,"episodes":[{"number":1},{"number":2}]}]}]}"#
+        );
+        assert!(HistoryPayload::new().validate().is_err());
+
+        let json = r#"{"added":{"movies":1,"shows":1,"episodes":2},"not_found":{"movies":[],"shows":[{"ids":{"simkl":1}}]}}"#;
+        let response: HistoryResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.added.total(), 4);
+        assert_eq!(response.not_found.len(), 1);
+    }
+
+    #[test]
+    fn test_remove_history_payload() {
+        let watched_at = Utc.with_ymd_and_hms(2024, 3, 1, 20, 0, 0).unwrap();
+        let payload = RemoveHistoryPayload::new()
+            .add_show(
+                SyncItem::new(MediaIds::new().with_simkl(17465)).with_episode(
+                    1,
+                    SyncEpisode {
+                        number: 3,
+                        watched_at: Some(watched_at),
+                    },
+                ),
+            )
+            .add_episode(MediaIds::new().with_simkl(999));
+        assert!(payload.validate().is_ok());
+        assert_eq!(
+            payload.build_url(),
+            "https://api.simkl.com/sync/history/remove"
+        );
+        assert_eq!(
+            payload.body().unwrap(),
+            r#"{"shows":[{"ids":{"simkl":17465},"seasons":[{"number":1,"episodes":[{"number":3}]}]}],"episodes":[{"ids":{"simkl":999}}]}"#
+        );
+
+        let json = r#"{"deleted":{"movies":0,"shows":1,"episodes":1},"not_found":{"episodes":[{"ids":{"simkl":999}}]}}"#;
+        let response: RemoveHistoryResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.deleted.total(), 2);
+        assert_eq!(response.not_found.episodes[0].ids.simkl, Some(999));
+    }
+
+    #[test]
+    fn test_ratings_payloads() {
+        assert!(UserRating::new(0).is_err());
+        assert!(UserRating::new(11).is_err());
+        assert!(serde_json::from_str::<RatedItem>(r#"{"ids":{"simkl":1},"rating":12}"#).is_err());
+
+        let rated_at = Utc.with_ymd_and_hms(2024, 3, 1, 20, 0, 0).unwrap();
+        let payload = RatingsPayload::new()
+            .add_movie(
+                RatedItem::new(
+                    MediaIds::new().with_simkl(53536),
+                    UserRating::new(9).unwrap(),
+                )
+                .with_rated_at(rated_at),
+            )
+            .add_anime(RatedItem::new(
+                MediaIds::new().with_mal(5114),
+                UserRating::new(10).unwrap(),
+            ));
+        assert!(payload.validate().is_ok());
+        assert_eq!(
+            payload.body().unwrap(),
+            r#"{"movies":[{"ids":{"simkl":53536},"rating":9,"rated_at":"2024-03-01T20:00:00Z"}],"anime":[{"ids":{"mal":5114},"rating":10}]}"#
+        );
+
+        let payload = RemoveRatingsPayload::new().add_show(MediaIds::new().with_simkl(17465));
+        assert_eq!(
+            payload.build_url(),
+            "https://api.simkl.com/sync/ratings/remove"
+        );
+        assert_eq!(
+            payload.body().unwrap(),
+            r#"{"shows":[{"ids":{"simkl":17465}}]}"#
+        );
+    }
+
+    #[test]
+    fn test_add_to_list_payload() {
+        let payload = AddToListPayload::new()
+            .add_movie(ListItem::new(
+                MediaIds::new().with_simkl(53536),
+                WatchStatus::PlanToWatch,
+            ))
+            .add_show(ListItem::new(
+                MediaIds::new().with_simkl(17465),
+                WatchStatus::NotInteresting,
+            ));
+        assert!(payload.validate().is_ok());
+        assert_eq!(
+            payload.body().unwrap(),
+            r#"{"movies":[{"ids":{"simkl":53536},"to":"plantowatch"}],"shows":[{"ids":{"simkl":17465},"to":"notinteresting"}]}"#
+        );
+        assert!(payload
+            .add_movie(ListItem::new(
+                MediaIds::new().with_simkl(1),
+                WatchStatus::Hold
+            ))
+            .validate()
+            .is_err());
+        assert_eq!(
+            get_all_items_request(
+                Some(String::from("shows")),
+                Some(WatchStatus::Watching),
+                None,
+                None
+            )
+            .unwrap(),
+            "https://api.simkl.com/sync/all-items/shows/watching"
+        );
+    }
+
+    #[test]
+    fn test_watched_check() {
+        let request = WatchedCheckRequest::new()
+            .add_item(WatchedCheckItem::new(
+                MediaIds::new().with_imdb(String::from("tt0944947")),
+            ))
+            .add_item(
+                WatchedCheckItem::new(MediaIds::new().with_mal(33352))
+                    .with_type(MediaType::Anime)
+                    .with_episode(None, 1),
+            )
+            .with_counters();
+        assert!(request.validate().is_ok());
+        assert_eq!(
+            request.build_url(),
+            "https://api.simkl.com/sync/watched?extended=counters"
+        );
+        assert_eq!(
+            request.body().unwrap(),
+            r#"[{"imdb":"tt0944947"},{"mal":33352,"type":"anime","episode":1}]"#
+        );
+
+        let json = r#"[{"imdb":"tt0944947","result":true,"list":"watching","last_watched_at":"2024-03-01T20:00:00Z","episodes_watched":5,"total_episodes":73},
+            {"mal":33352,"type":"anime","episode":1,"result":"not_found"}]"#;
+        let results: Vec<WatchedCheckResult> = serde_json::from_str(json).unwrap();
+        assert_eq!(results[0].result, WatchedResult::Watched);
+        assert_eq!(results[0].list, Some(WatchStatus::Watching));
+        assert_eq!(results[0].episodes_watched, Some(5));
+        assert_eq!(results[1].result, WatchedResult::NotFound);
+        assert_eq!(results[1].item.ids.mal, Some(33352));
+    }
+
+    #[test]
+    fn test_all_items() {
+        let date_from = Utc.with_ymd_and_hms(2024, 3, 1, 20, 0, 0).unwrap();
+        let request = AllItemsRequest::new()
+            .with_type(MediaType::Show)
+            .with_status(WatchStatus::Watching)
+            .with_date_from(date_from)
+            .with_episode_watched_at()
+            .with_memos();
+        assert!(request.validate().is_ok());
+        assert_eq!(
+            request.build_url(),
+            "https://api.simkl.com/sync/all-items/shows/watching?date_from=2024-03-01T20%3A00%3A00Z&episode_watched_at=yes&memos=yes"
+        );
+        assert!(AllItemsRequest::new()
+            .with_status(WatchStatus::Hold)
+            .validate()
+            .is_err());
+
+        let json = r#"{"shows":[{"added_to_watchlist_at":"2024-01-01T10:00:00Z","last_watched_at":"2024-03-01T20:00:00Z",
+            "user_rating":8,"status":"watching","last_watched":"S01E02","next_to_watch":"S01E03","watched_episodes_count":2,
+            "total_episodes_count":73,"not_aired_episodes_count":0,"show":{"title":"Game of Thrones","year":2011,
+            "ids":{"simkl":17465,"slug":"game-of-thrones","imdb":"tt0944947"}},
+            "seasons":[{"number":1,"episodes":[{"number":1,"watched_at":"2024-02-28T20:00:00Z"},{"number":2}]}]}],
+            "anime":[{"status":"completed","anime_type":"movie","user_rating":11,"show":{"title":"Your Name.","ids":{"simkl":433548}}}]}"#;
+        let items = AllItems::from_body(json).unwrap();
+        let got = &items.shows[0];
+        assert_eq!(got.status, Some(WatchStatus::Watching));
+        assert_eq!(got.user_rating, UserRating::new(8).ok());
+        assert_eq!(got.next_episode(), Some((Some(1), 3)));
+        assert!(got.is_episode_watched(1, 2));
+        assert_eq!(got.ids().unwrap().simkl, Some(17465));
+        assert_eq!(items.anime[0].anime_type, Some(AnimeType::Movie));
+        assert_eq!(items.anime[0].user_rating, None);
+        assert!(AllItems::from_body("null").unwrap().is_empty());
+    }
 }
@@ -0,0 +1,421 @@
+//! Payloads reverting a sync change, built from the library as it was before the change.
+//!
+//! History adds are reverted by removing what was not watched before, then adding back what was. A media missing from
+//! a synced library was not in the user library, its history and rating are removed. Items whose previous state
+//! cannot be told from the library (not found in a library never synced, standalone episodes, shows without their
+//! watched episodes) are reported as unresolved rather than removed, since a removal could drop history that was there
+//! before.
+
+use crate::{
+    error::Result,
+    library::Library,
+    request::{PreparedRequest, SimklRequest},
+    sync::{
+        section, sections, AddToListPayload, HistoryPayload, LibraryItem, ListItem, RatedItem,
+        RatingsPayload, RemoveHistoryPayload, RemoveRatingsPayload, SyncEpisode, SyncItem,
+        WatchedSeason,
+    },
+    MediaIds, MediaType, WatchStatus,
+};
+
+/// Item of the payload the plan does not revert
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unresolved {
+    pub media_type: MediaType,
+    pub ids: MediaIds,
+    pub reason: String,
+}
+
+/// Payloads to send in the order of the fields, empty payloads are skipped
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct UndoPlan {
+    pub remove_history: RemoveHistoryPayload,
+    pub add_history: HistoryPayload,
+    pub list: AddToListPayload,
+    pub ratings: RatingsPayload,
+    pub remove_ratings: RemoveRatingsPayload,
+    /// Items left out of the payloads, to revert by hand
+    pub unresolved: Vec<Unresolved>,
+}
+
+impl UndoPlan {
+    /// Nothing to send, there may still be unresolved items
+    pub fn is_empty(&self) -> bool {
+        self.remove_history.is_empty()
+            && self.add_history.is_empty()
+            && self.list.is_empty()
+            && self.ratings.is_empty()
+            && self.remove_ratings.is_empty()
+    }
+
+    /// Every item of the payload is reverted by the plan
+    pub fn is_complete(&self) -> bool {
+        self.unresolved.is_empty()
+    }
+
+    /// Requests to send, in order
+    pub fn requests(&self) -> Result<Vec<PreparedRequest>> {
+        let mut requests = Vec::new();
+        if !self.remove_history.is_empty() {
+            requests.push(self.remove_history.prepare()?);
+        }
+        if !self.add_history.is_empty() {
+            requests.push(self.add_history.prepare()?);
+        }
+        if !self.list.is_empty() {
+            requests.push(self.list.prepare()?);
+        }
+        if !self.ratings.is_empty() {
+            requests.push(self.ratings.prepare()?);
+        }
+        if !self.remove_ratings.is_empty() {
+            requests.push(self.remove_ratings.prepare()?);
+        }
+        Ok(requests)
+    }
+
+    fn unresolved(&mut self, media_type: MediaType, ids: &MediaIds, reason: &str) {
+        self.unresolved.push(Unresolved {
+            media_type,
+            ids: ids.clone(),
+            reason: reason.to_string(),
+        });
+    }
+
+    fn remove_history(&mut self, media_type: MediaType, item: SyncItem) {
+        let p = &mut self.remove_history;
+        section(media_type, &mut p.movies, &mut p.shows, &mut p.anime).push(item.without_dates());
+    }
+
+    fn move_to(&mut self, media_type: MediaType, ids: &MediaIds, status: WatchStatus) {
+        let p = &mut self.list;
+        section(media_type, &mut p.movies, &mut p.shows, &mut p.anime)
+            .push(ListItem::new(ids.clone(), status));
+    }
+
+    fn rate(&mut self, media_type: MediaType, ids: &MediaIds, previous: &LibraryItem) {
+        match previous.user_rating {
+            Some(rating) => {
+                let mut item = RatedItem::new(ids.clone(), rating);
+                item.rated_at = previous.user_rated_at;
+                let p = &mut self.ratings;
+                section(media_type, &mut p.movies, &mut p.shows, &mut p.anime).push(item);
+            }
+            None => self.remove_rating(media_type, ids),
+        }
+    }
+
+    fn remove_rating(&mut self, media_type: MediaType, ids: &MediaIds) {
+        let p = &mut self.remove_ratings;
+        section(media_type, &mut p.movies, &mut p.shows, &mut p.anime)
+            .push(SyncItem::new(ids.clone()));
+    }
+
+    /// Add back the watched episodes and the list of an item
+    fn restore(&mut self, media_type: MediaType, ids: &MediaIds, previous: &LibraryItem) {
+        let mut item = SyncItem::new(ids.clone());
+        for season in &previous.seasons {
+            item = season.episodes.iter().fold(item, |item, e| {
+                item.with_episode(
+                    season.number,
+                    SyncEpisode {
+                        number: e.number,
+                        watched_at: e.watched_at,
+                    },
+                )
+            });
+        }
+        if media_type == MediaType::Movie && previous.status == Some(WatchStatus::Completed) {
+            item.watched_at = previous.last_watched_at;
+            let p = &mut self.add_history;
+            p.movies.push(item);
+        } else if !item.seasons.is_empty() {
+            let p = &mut self.add_history;
+            section(media_type, &mut p.movies, &mut p.shows, &mut p.anime).push(item);
+        }
+        if let Some(status) = previous.status {
+            self.move_to(media_type, ids, status);
+        }
+    }
+}
+
+/// Episode watched before the change, anime episodes without season are looked up in every season
+fn watched_before(seasons: &[WatchedSeason], season: Option<u16>, episode: u16) -> bool {
+    seasons
+        .iter()
+        .filter(|s| season.is_none() || season == Some(s.number))
+        .any(|s| s.episodes.iter().any(|e| e.number == episode))
+}
+
+/// Every watched episode of the item is listed, so its history can be removed then added back
+fn episodes_known(previous: &LibraryItem) -> bool {
+    let listed: usize = previous.seasons.iter().map(|s| s.episodes.len()).sum();
+    match previous.watched_episodes_count {
+        Some(count) => listed >= count as usize,
+        None => listed > 0 || previous.last_watched_at.is_none(),
+    }
+}
+
+const NOT_IN_LIBRARY: &str = "not in the library, its previous state is unknown";
+const EPISODES_UNKNOWN: &str = "watched episodes missing from the library";
+
+/// Change that can be reverted
+pub trait Undo {
+    /// Payloads reverting this change, `library` is the state before it was sent
+    fn undo(&self, library: &Library) -> UndoPlan;
+}
+
+impl Undo for HistoryPayload {
+    fn undo(&self, library: &Library) -> UndoPlan {
+        let mut plan = UndoPlan::default();
+        for (media_type, item) in sections(&self.movies, &self.shows, &self.anime) {
+            let Some(previous) = library.get(media_type, &item.ids) else {
+                if library.is_synced() {
+                    plan.remove_history(media_type, SyncItem::new(item.ids.clone()));
+                } else {
+                    plan.unresolved(media_type, &item.ids, NOT_IN_LIBRARY);
+                }
+                continue;
+            };
+            let episodes_only =
+                !item.is_whole() && item.seasons.iter().all(|s| !s.episodes.is_empty());
+            if episodes_only && previous.status.is_some() {
+                // only remove the episodes that were not watched
+                let mut removed = SyncItem::new(item.ids.clone());
+                for season in &item.seasons {
+                    let new =
+                        season.episodes.iter().map(|e| e.number).filter(|e| {
+                            !watched_before(&previous.seasons, Some(season.number), *e)
+                        });
+                    removed = removed.with_episodes(season.number, new);
+                }
+                let new: Vec<u16> = item
+                    .episodes
+                    .iter()
+                    .map(|e| e.number)
+                    .filter(|e| !watched_before(&previous.seasons, None, *e))
+                    .collect();
+                removed = removed.with_absolute_episodes(new);
+                removed.seasons.retain(|s| !s.episodes.is_empty());
+                if !removed.is_whole() {
+                    plan.remove_history(media_type, removed);
+                }
+                if let Some(status) = previous.status {
+                    plan.move_to(media_type, &item.ids, status);
+                }
+            } else if media_type == MediaType::Movie
+                && previous.status == Some(WatchStatus::Completed)
+            {
+                continue;
+            } else if !episodes_known(previous) {
+                plan.unresolved(media_type, &item.ids, EPISODES_UNKNOWN);
+            } else {
+                plan.remove_history(media_type, SyncItem::new(item.ids.clone()));
+                plan.restore(media_type, &item.ids, previous);
+                if previous.user_rating.is_some() {
+                    plan.rate(media_type, &item.ids, previous);
+                }
+            }
+        }
+        for episode in &self.episodes {
+            plan.unresolved(
+                MediaType::Episode,
+                &episode.ids,
+                "episode identified by its own ids",
+            );
+        }
+        plan
+    }
+}
+
+impl Undo for RemoveHistoryPayload {
+    fn undo(&self, library: &Library) -> UndoPlan {
+        let mut plan = UndoPlan::default();
+        for (media_type, item) in sections(&self.movies, &self.shows, &self.anime) {
+            match library.get(media_type, &item.ids) {
+                // nothing was watched, nothing to add back
+                None if library.is_synced() => {}
+                None => plan.unresolved(media_type, &item.ids, NOT_IN_LIBRARY),
+                Some(previous) if !episodes_known(previous) => {
+                    plan.unresolved(media_type, &item.ids, EPISODES_UNKNOWN)
+                }
+                Some(previous) => {
+                    plan.restore(media_type, &item.ids, previous);
+                    if previous.user_rating.is_some() {
+                        plan.rate(media_type, &item.ids, previous);
+                    }
+                }
+            }
+        }
+        for episode in &self.episodes {
+            plan.unresolved(
+                MediaType::Episode,
+                &episode.ids,
+                "episode identified by its own ids",
+            );
+        }
+        plan
+    }
+}
+
+impl Undo for RatingsPayload {
+    fn undo(&self, library: &Library) -> UndoPlan {
+        let mut plan = UndoPlan::default();
+        for (media_type, item) in sections(&self.movies, &self.shows, &self.anime) {
+            match library.get(media_type, &item.ids) {
+                Some(previous) => plan.rate(media_type, &item.ids, previous),
+                None if library.is_synced() => plan.remove_rating(media_type, &item.ids),
+                None => plan.unresolved(media_type, &item.ids, NOT_IN_LIBRARY),
+            }
+        }
+        plan
+    }
+}
+
+impl Undo for RemoveRatingsPayload {
+    fn undo(&self, library: &Library) -> UndoPlan {
+        let mut plan = UndoPlan::default();
+        for (media_type, item) in sections(&self.movies, &self.shows, &self.anime) {
+            match library.get(media_type, &item.ids) {
+                Some(previous) if previous.user_rating.is_some() => {
+                    plan.rate(media_type, &item.ids, previous)
+                }
+                Some(_) => {}
+                None if library.is_synced() => {}
+                None => plan.unresolved(media_type, &item.ids, NOT_IN_LIBRARY),
+            }
+        }
+        plan
+    }
+}
+
+impl Undo for AddToListPayload {
+    fn undo(&self, library: &Library) -> UndoPlan {
+        let mut plan = UndoPlan::default();
+        for (media_type, item) in sections(&self.movies, &self.shows, &self.anime) {
+            match library.get(media_type, &item.ids).and_then(|p| p.status) {
+                Some(status) => plan.move_to(media_type, &item.ids, status),
+                // removing the history takes the media out of every list
+                None if library.is_synced() => {
+                    plan.remove_history(media_type, SyncItem::new(item.ids.clone()))
+                }
+                None => plan.unresolved(media_type, &item.ids, NOT_IN_LIBRARY),
+            }
+        }
+        plan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::{Activities, AllItems, LibraryMedia, UserRating, WatchedEpisode};
+
+    fn library() -> Library {
+        let mut library = Library::new();
+        library.apply_delta(AllItems {
+            shows: vec![LibraryItem {
+                status: Some(WatchStatus::Watching),
+                user_rating: Some(UserRating::new(7).unwrap()),
+                show: Some(LibraryMedia {
+                    ids: MediaIds::new().with_simkl(1),
+                    ..Default::default()
+                }),
+                seasons: vec![WatchedSeason {
+                    number: 1,
+                    episodes: vec![WatchedEpisode {
+                        number: 1,
+                        watched_at: None,
+                    }],
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        library.record_activities(Activities::default());
+        library
+    }
+
+    #[test]
+    fn test_undo_history() {
+        let payload = HistoryPayload::new()
+            .add_show(SyncItem::new(MediaIds::new().with_simkl(1)).with_episodes(1, [1, 2]))
+            .add_movie(SyncItem::new(MediaIds::new().with_simkl(2)));
+        let plan = payload.undo(&library());
+        assert_eq!(
+            plan.remove_history.shows,
+            vec![SyncItem::new(MediaIds::new().with_simkl(1)).with_episodes(1, [2])]
+        );
+        // watched by mistake: not in the library before
+        assert_eq!(
+            plan.remove_history.movies,
+            vec![SyncItem::new(MediaIds::new().with_simkl(2))]
+        );
+        assert!(plan.is_complete());
+        assert_eq!(
+            plan.list.shows,
+            vec![ListItem::new(
+                MediaIds::new().with_simkl(1),
+                WatchStatus::Watching
+            )]
+        );
+        assert_eq!(plan.requests().unwrap().len(), 2);
+
+        // a library never synced cannot tell
+        let plan = payload.undo(&Library::new());
+        assert_eq!(plan.unresolved.len(), 2);
+        assert!(plan.is_empty());
+    }
+
+    #[test]
+    fn test_undo_ratings_and_list() {
+        let payload = RatingsPayload::new()
+            .add_show(RatedItem::new(
+                MediaIds::new().with_simkl(1),
+                UserRating::new(9).unwrap(),
+            ))
+            .add_movie(RatedItem::new(
+                MediaIds::new().with_simkl(2),
+                UserRating::new(9).unwrap(),
+            ));
+        let plan = payload.undo(&library());
+        assert_eq!(plan.ratings.shows[0].rating.get(), 7);
+        assert_eq!(
+            plan.remove_ratings.movies,
+            vec![SyncItem::new(MediaIds::new().with_simkl(2))]
+        );
+        assert!(plan.is_complete());
+
+        let payload = AddToListPayload::new().add_show(ListItem::new(
+            MediaIds::new().with_simkl(1),
+            WatchStatus::Dropped,
+        ));
+        let plan = payload.undo(&library());
+        assert_eq!(plan.list.shows[0].to, WatchStatus::Watching);
+        assert!(!plan.is_empty());
+    }
+
+    #[test]
+    fn test_undo_without_episode_detail() {
+        let mut library = library();
+        library.apply_delta(AllItems {
+            shows: vec![LibraryItem {
+                status: Some(WatchStatus::Completed),
+                watched_episodes_count: Some(62),
+                show: Some(LibraryMedia {
+                    ids: MediaIds::new().with_simkl(3),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        let payload = HistoryPayload::new()
+            .add_show(SyncItem::new(MediaIds::new().with_simkl(3)))
+            .add_episode(MediaIds::new().with_simkl(4), None);
+        let plan = payload.undo(&library);
+        assert!(plan.is_empty());
+        assert_eq!(plan.unresolved.len(), 2);
+        assert!(!plan.is_complete());
+    }
+}
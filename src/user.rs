@@ -1,7 +1,13 @@
 use chrono::{DateTime, Utc};
 use serde::Deserialize;
 
-use crate::{StandardMediaObject, API_URL};
+use crate::{
+    error::Result,
+    response::SimklResponse,
+    scrobble::{parse_playback_response, PlaybackEpisode},
+    sync::LibraryMedia,
+    API_URL,
+};
 
 /// User settings, requires those headers in your request:
 /// * `Content-Type:application/json`
@@ -136,7 +142,7 @@ pub struct LastWatchedArts {
 pub fn get_last_watched_arts(user_id: u32, client_id: String) -> String {
     let mut result = String::with_capacity(128);
     result.push_str(API_URL);
-    result.push_str("users/recently-watched-background/");
+    result.push_str("/users/recently-watched-background/");
     result.push_str(&user_id.to_string());
     result.push_str("?client_id=");
     result.push_str(&client_id);
@@ -149,7 +155,7 @@ pub fn get_last_watched_arts(user_id: u32, client_id: String) -> String {
 pub fn get_last_watched_image_request(user_id: u32, fanart: bool, client_id: String) -> String {
     let mut result = String::with_capacity(128);
     result.push_str(API_URL);
-    result.push_str("users/recently-watched-background/");
+    result.push_str("/users/recently-watched-background/");
     result.push_str(&user_id.to_string());
     result.push_str("?image=");
     if fanart {
@@ -164,9 +170,20 @@ pub fn get_last_watched_image_request(user_id: u32, fanart: bool, client_id: Str
 
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
 pub struct CheckinResponse {
-    pub movie: Option<StandardMediaObject>,
-    pub show: Option<StandardMediaObject>,
-    pub anime: Option<StandardMediaObject>,
+    pub watched_at: Option<DateTime<Utc>>,
+    /// End of the checkin, when the item is added to the history
+    pub expires_at: Option<DateTime<Utc>>,
+    pub movie: Option<LibraryMedia>,
+    pub show: Option<LibraryMedia>,
+    pub anime: Option<LibraryMedia>,
+    pub episode: Option<PlaybackEpisode>,
+}
+
+impl CheckinResponse {
+    /// Parse the response of a `scrobble::CheckinRequest`
+    pub fn from_response(response: SimklResponse) -> Result<Self> {
+        parse_playback_response(response)
+    }
 }
 
 #[cfg(test)]